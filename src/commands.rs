@@ -1,4 +1,4 @@
-automod::dir!("src/commands");
+automod::dir!(pub(crate) "src/commands");
 
 use anyhow::Result;
 use clap::Subcommand;
@@ -11,6 +11,8 @@ pub(crate) enum Command {
     ExtCommand(ExtCommand),
 
     Convert(convert::Args),
+    Validate(validate::Args),
+    Schema(schema::Args),
     Version(version::Args),
 }
 
@@ -41,7 +43,7 @@ pub(crate) async fn run(command: Command, config: Config) -> Result<()> {
     match command {
         Command::ExtCommand(command) => {
             let podman = Podman::new(&config).await?;
-            let file = compose::parse(&config, false)?;
+            let file = compose::parse(&config, &compose::Options::default())?.report();
 
             match command {
                 ExtCommand::Up(args) => up::run(args, &podman, &file, &config).await,
@@ -65,7 +67,9 @@ pub(crate) async fn run(command: Command, config: Config) -> Result<()> {
                 ExtCommand::Ls(args) => ls::run(args, &podman).await,
             }?;
         }
-        Command::Convert(args) => convert::run(args, &config)?,
+        Command::Convert(args) => convert::run(args, &config).await?,
+        Command::Validate(args) => validate::run(args, &config)?,
+        Command::Schema(args) => schema::run(args)?,
         Command::Version(args) => version::run(args),
     }
 
@@ -1,21 +1,39 @@
 use std::{
-    fs,
+    collections::VecDeque,
+    env,
+    ffi::OsStr,
+    fmt::Write,
+    fs, iter, mem,
     path::{Path, PathBuf},
 };
 
-use anyhow::{anyhow, Context, Error, Result};
-use clap::ValueEnum;
-use indexmap::IndexSet;
+use anyhow::{anyhow, bail, Context, Error, Result};
+use console::style;
+use futures::{future, stream::FuturesUnordered, StreamExt, TryStreamExt};
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
 use path_absolutize::Absolutize;
+use regex::Regex;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
 
-use crate::{compose, config::Config};
+use crate::{
+    compose::{
+        self,
+        types::{Compose, ServiceVolumeType},
+        Options, UnknownProperties,
+    },
+    config::Config,
+    podman::{types::Image, Podman},
+    utils::{warning, Digest, Failure},
+};
 
 /// Converts the Compose file to platform's canonical format
 #[derive(clap::Args, Debug)]
 #[command(alias = "config", next_display_order = None)]
 pub(crate) struct Args {
-    /// Format the output
-    #[arg(long, value_enum, default_value_t = Format::Yaml)]
+    /// Format the output: yaml, json, toml, or a template like "{name}: {image}" with --images
+    #[arg(long, value_parser = parse_format, default_value = "yaml")]
     format: Format,
 
     /// Only validate the configuration, don't print anything
@@ -26,14 +44,103 @@ pub(crate) struct Args {
     #[arg(long)]
     no_interpolate: bool,
 
+    /// Fail if the variable is unset or empty, before interpolating
+    #[arg(long, value_name = "VARIABLE")]
+    require: Vec<String>,
+
+    /// Don't check the consistency of the configuration, e.g. that services have an image
+    #[arg(long)]
+    no_consistency: bool,
+
+    /// Fail on unsupported/unknown properties
+    #[arg(long, conflicts_with = "ignore_unknown")]
+    strict: bool,
+
+    /// Don't warn about unsupported/unknown properties
+    #[arg(long)]
+    ignore_unknown: bool,
+
+    /// Keep the original key order and structure, without normalizing
+    #[arg(
+        long,
+        conflicts_with_all = ["resolve_image_digests", "check_images_exist", "select", "relative_paths", "anonymize", "prefix_resources", "since", "services", "volumes", "list_dangling", "profiles", "images", "hash", "split"]
+    )]
+    no_normalize: bool,
+
+    /// Only merge the Compose files as they are written, implying --no-normalize and
+    /// --no-interpolate
+    #[arg(
+        long,
+        conflicts_with_all = ["resolve_extends", "resolve_image_digests", "check_images_exist", "select", "relative_paths", "anonymize", "prefix_resources", "since", "services", "volumes", "list_dangling", "profiles", "images", "hash", "print_resolved_env", "diff", "split"]
+    )]
+    merge_only: bool,
+
+    /// Inline the services extended with `extends` when not normalizing
+    #[arg(long, requires = "no_normalize")]
+    resolve_extends: bool,
+
+    /// Pin image references to their digests
+    #[arg(long, conflicts_with = "no_interpolate")]
+    resolve_image_digests: bool,
+
+    /// Check that the images can be pulled from their registry, without pulling them
+    #[arg(long, conflicts_with = "no_interpolate")]
+    check_images_exist: bool,
+
+    /// Only keep the given services
+    #[arg(long, value_name = "SERVICE")]
+    select: Vec<String>,
+
+    /// Also keep the dependencies of the selected services
+    #[arg(long, requires = "select")]
+    with_deps: bool,
+
+    /// Rewrite the bind mount sources inside the project directory relative to it
+    #[arg(long)]
+    relative_paths: bool,
+
+    /// Replace the environment values that look like secrets and the secret files and contents
+    /// with "***"
+    #[arg(long)]
+    anonymize: bool,
+
+    /// Also anonymize the environment variables matching the pattern, e.g. "*_CREDENTIALS"
+    #[arg(long, value_name = "PATTERN", requires = "anonymize")]
+    anonymize_pattern: Vec<String>,
+
+    /// Rename the networks and volumes after the resources created for them, with the project name
+    /// as prefix
+    #[arg(long)]
+    prefix_resources: bool,
+
+    /// Print the variables referenced by the configuration, one per line
+    #[arg(long)]
+    variables: bool,
+
+    /// Also print the default value of the variables, e.g. "TAG=latest"
+    #[arg(long, requires = "variables")]
+    with_defaults: bool,
+
     /// Print the service names, one per line
     #[arg(long)]
     services: bool,
 
+    /// Print the services as a table with their image, first published port and profiles
+    #[arg(long, requires = "services")]
+    table: bool,
+
     /// Print the volume names, one per line
     #[arg(long)]
     volumes: bool,
 
+    /// Print the networks, volumes and secrets that none of the enabled services use, grouped by
+    /// type
+    #[arg(
+        long,
+        conflicts_with_all = ["select", "since", "services", "volumes", "profiles", "images", "hash", "print_resolved_env"]
+    )]
+    list_dangling: bool,
+
     /// Print the profile names, one per line
     #[arg(long)]
     profiles: bool,
@@ -42,22 +149,960 @@ pub(crate) struct Args {
     #[arg(long)]
     images: bool,
 
+    /// Print the SHA-256 hash of the configuration or of a single service
+    #[arg(long, value_name = "SERVICE", num_args = 0..=1, default_missing_value = "*")]
+    hash: Option<String>,
+
+    /// Print the variables referenced by the configuration with their resolved value
+    #[arg(long, conflicts_with_all = ["no_interpolate", "no_normalize"])]
+    print_resolved_env: bool,
+
+    /// Don't mask the values of variables that look like secrets
+    #[arg(long, requires = "print_resolved_env")]
+    show_secrets: bool,
+
+    /// Only keep the services that are new or changed since a previous output of convert
+    #[arg(long, value_name = "FILE")]
+    since: Option<PathBuf>,
+
+    /// Print the changes made by the last file to the ones before it as a unified diff
+    #[arg(
+        long,
+        conflicts_with_all = ["no_normalize", "since", "resolve_image_digests", "variables", "services", "volumes", "list_dangling", "profiles", "images", "hash", "print_resolved_env", "output", "split"]
+    )]
+    diff: bool,
+
+    /// Remove the obsolete `version` element
+    #[arg(long)]
+    strip_version: bool,
+
+    /// Keep the top-level sections declared in the Compose files even when they end up empty
+    #[arg(long)]
+    keep_empty: bool,
+
+    /// Print the JSON on a single line
+    #[arg(long)]
+    compact: bool,
+
+    /// Sort the keys of every mapping, e.g. the services, leaving the order of lists untouched
+    #[arg(long, alias = "sort")]
+    deterministic: bool,
+
     /// Save to file (default to stdout)
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Write each service with the resources and services it uses to its own file, e.g. "web.yaml"
+    #[arg(long, requires = "output_dir", conflicts_with = "output")]
+    split: bool,
+
+    /// Directory to write the files to with --split
+    #[arg(long, value_name = "DIR", requires = "split")]
+    output_dir: Option<PathBuf>,
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(Clone, Debug)]
 enum Format {
     Yaml,
-    Json,
+    Json { compact: bool },
+    Toml,
+    Template(Vec<Placeholder>),
+}
+
+#[derive(Clone, Debug)]
+enum Placeholder {
+    Text(String),
+    Name,
+    Image,
+}
+
+fn parse_template(template: &str) -> Result<Vec<Placeholder>> {
+    let mut placeholders = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.as_str().starts_with(c) => {
+                chars.next();
+                text.push(c);
+            }
+            '{' => {
+                let (name, rest) = chars
+                    .as_str()
+                    .split_once('}')
+                    .ok_or_else(|| anyhow!("Unclosed placeholder in \"{template}\""))?;
+                let placeholder = match name.trim() {
+                    "name" => Placeholder::Name,
+                    "image" => Placeholder::Image,
+                    name => bail!("Unknown placeholder \"{name}\", expected one of: name, image"),
+                };
+
+                if !text.is_empty() {
+                    placeholders.push(Placeholder::Text(mem::take(&mut text)));
+                }
+
+                placeholders.push(placeholder);
+                chars = rest.chars();
+            }
+            '}' => bail!("Unmatched \"}}\" in \"{template}\""),
+            c => text.push(c),
+        }
+    }
+
+    if !text.is_empty() {
+        placeholders.push(Placeholder::Text(text));
+    }
+
+    Ok(placeholders)
+}
+
+fn parse_format(s: &str) -> Result<Format> {
+    match s {
+        "yaml" => Ok(Format::Yaml),
+        "json" => Ok(Format::Json { compact: false }),
+        "toml" => Ok(Format::Toml),
+        _ if s.contains(['{', '}']) => parse_template(s).map(Format::Template),
+        _ => bail!("expected yaml, json, toml or a template"),
+    }
+}
+
+fn toml_key(key: &Value) -> Result<String> {
+    match key {
+        Value::String(key) => Ok(key.clone()),
+        Value::Bool(key) => Ok(key.to_string()),
+        Value::Number(key) => Ok(key.to_string()),
+        _ => bail!("{key:?} cannot be used as a TOML key"),
+    }
+}
+
+fn toml_value(value: Value) -> Result<toml_edit::Value> {
+    Ok(match value {
+        Value::Null => toml_edit::InlineTable::new().into(),
+        Value::Bool(value) => value.into(),
+        Value::Number(value) => {
+            if let Some(value) = value.as_i64() {
+                value.into()
+            } else if let Some(value) = value.as_f64().filter(|_| !value.is_u64()) {
+                value.into()
+            } else {
+                bail!("{value} is too large to be represented in TOML");
+            }
+        }
+        Value::String(value) => value.into(),
+        Value::Sequence(values) => values
+            .into_iter()
+            .map(toml_value)
+            .collect::<Result<toml_edit::Array>>()?
+            .into(),
+        Value::Mapping(values) => values
+            .into_iter()
+            .map(|(key, value)| Ok((toml_key(&key)?, toml_value(value)?)))
+            .collect::<Result<toml_edit::InlineTable>>()?
+            .into(),
+        Value::Tagged(value) => toml_value(value.value)?,
+    })
+}
+
+fn toml_table(values: Mapping) -> Result<toml_edit::Table> {
+    let mut table = toml_edit::Table::new();
+    table.set_implicit(true);
+
+    for (key, value) in values {
+        table.insert(&toml_key(&key)?, toml_item(value)?);
+    }
+
+    Ok(table)
+}
+
+// Null values, e.g. `volumes: { data: }`, have no TOML equivalent and become empty tables
+fn toml_item(value: Value) -> Result<toml_edit::Item> {
+    Ok(match value {
+        Value::Null => toml_edit::Item::Table(toml_edit::Table::new()),
+        Value::Mapping(values) => toml_edit::Item::Table(toml_table(values)?),
+        Value::Sequence(values) if !values.is_empty() && values.iter().all(Value::is_mapping) => {
+            let mut tables = toml_edit::ArrayOfTables::new();
+
+            for value in values {
+                if let Value::Mapping(values) = value {
+                    tables.push(toml_table(values)?);
+                }
+            }
+
+            toml_edit::Item::ArrayOfTables(tables)
+        }
+        value => toml_edit::Item::Value(toml_value(value)?),
+    })
+}
+
+async fn resolve_image_digests(file: &mut Compose, config: &Config) -> Result<()> {
+    let podman = &Podman::new(config).await?;
+    let digests = file
+        .services
+        .values()
+        .filter_map(|service| service.image.as_ref())
+        .filter(|image| !image.contains('@'))
+        .collect::<IndexSet<_>>()
+        .into_iter()
+        .map(|image| async move {
+            if podman.force_run(["image", "exists", image]).await.is_err() {
+                podman.force_run(["pull", "--quiet", image]).await?;
+            }
+
+            let output = podman.force_run(["image", "inspect", image]).await?;
+            let digest = serde_json::from_str::<VecDeque<Image>>(&output)?
+                .pop_front()
+                .and_then(|inspected| repository_digest(image, inspected.repo_digests))
+                .ok_or_else(|| {
+                    anyhow!("Image \"{image}\" does not have a digest from its repository")
+                })?;
+
+            Ok::<_, Error>((image.clone(), digest))
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect::<IndexMap<_, _>>()
+        .await?;
+
+    for service in file.services.values_mut() {
+        if let Some(digest) = service.image.as_ref().and_then(|image| digests.get(image)) {
+            service.image = Some(digest.clone());
+        }
+    }
+
+    Ok(())
 }
 
-pub(crate) fn run(args: Args, config: &Config) -> Result<()> {
-    let file = compose::parse(config, args.no_interpolate)?;
+/// Finds the digest of an image from the repository it is referenced by, as an image tagged under
+/// several names has a digest for each of their repositories
+fn repository_digest(image: &str, digests: VecDeque<String>) -> Option<String> {
+    let name = match image.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => name,
+        _ => image,
+    };
+    // Without a registry, the name is a short one that Podman resolves from any of the registries
+    let has_registry = name
+        .split_once('/')
+        .is_some_and(|(registry, _)| registry.contains(['.', ':']) || registry == "localhost");
+
+    digests.into_iter().find(|digest| {
+        digest.split_once('@').is_some_and(|(repository, _)| {
+            if has_registry {
+                repository == name
+            } else {
+                repository.split_once('/').is_some_and(|(_, path)| {
+                    path == name || path.strip_prefix("library/") == Some(name)
+                })
+            }
+        })
+    })
+}
+
+async fn check_images_exist(file: &Compose, config: &Config) -> Result<()> {
+    let podman = &Podman::new(config).await?;
+    let authfile = env::var_os("DOCKER_CONFIG")
+        .map(|directory| PathBuf::from(directory).join("config.json"))
+        .filter(|authfile| authfile.exists());
+    let authfile = authfile.as_ref();
+    let failures = file
+        .services
+        .values()
+        .filter_map(|service| service.image.as_ref())
+        .collect::<IndexSet<_>>()
+        .into_iter()
+        .map(|image| async move {
+            let mut args = vec![OsStr::new("manifest"), OsStr::new("inspect")];
+
+            if let Some(authfile) = authfile {
+                args.extend([OsStr::new("--authfile"), authfile.as_os_str()]);
+            }
+
+            args.push(OsStr::new(image));
+
+            podman.force_run(args).await.err().map(|err| {
+                let message = err.root_cause().to_string();
+                let lowercase = message.to_lowercase();
+                let reason = if ["unauthorized", "authentication required", "denied"]
+                    .iter()
+                    .any(|reason| lowercase.contains(reason))
+                {
+                    String::from("authentication failed")
+                } else if ["timeout", "timed out", "deadline exceeded"]
+                    .iter()
+                    .any(|reason| lowercase.contains(reason))
+                {
+                    String::from("timed out")
+                } else if ["manifest unknown", "not found"]
+                    .iter()
+                    .any(|reason| lowercase.contains(reason))
+                {
+                    String::from("not found")
+                } else {
+                    message
+                        .lines()
+                        .last()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string()
+                };
+
+                format!("\"{image}\": {reason}")
+            })
+        })
+        .collect::<FuturesUnordered<_>>()
+        .filter_map(future::ready)
+        .collect::<Vec<_>>()
+        .await;
+
+    if !failures.is_empty() {
+        bail!(
+            "{} cannot be pulled:\n{}",
+            if failures.len() == 1 {
+                String::from("1 image")
+            } else {
+                format!("{} images", failures.len())
+            },
+            failures
+                .into_iter()
+                .sorted()
+                .map(|failure| format!("  {failure}"))
+                .join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+fn select(file: &mut Compose, services: Vec<String>, with_deps: bool) -> Result<()> {
+    let mut selected = IndexSet::new();
+    let mut services = VecDeque::from(services);
+
+    while let Some(name) = services.pop_front() {
+        let service = file
+            .services
+            .get(&name)
+            .ok_or_else(|| anyhow!("No such service: \"{name}\""))?;
+
+        if with_deps {
+            services.extend(
+                service
+                    .depends_on
+                    .keys()
+                    .filter(|dependency| !selected.contains(*dependency))
+                    .cloned(),
+            );
+        }
+
+        selected.insert(name);
+    }
+
+    file.services.retain(|name, _| selected.contains(name));
+
+    for service in file.services.values_mut() {
+        service
+            .depends_on
+            .retain(|dependency, _| selected.contains(dependency));
+    }
+
+    compose::prune(file);
+
+    Ok(())
+}
+
+/// Keeps the services whose definition differs from the one in a previous output, the removed
+/// ones being reported
+fn since(file: &mut Compose, path: &Path) -> Result<()> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| Failure::NotFound.error(format_args!("{} not found", path.display())))?;
+    let previous = serde_yaml::from_str::<Compose>(&contents)
+        .with_context(|| anyhow!("{} is not an output of convert", path.display()))?;
+
+    for name in previous.services.keys() {
+        if !file.services.contains_key(name) {
+            warning!("Service \"{name}\" was removed since {}", path.display());
+        }
+    }
+
+    let changed = file
+        .services
+        .iter()
+        .filter(|(name, service)| {
+            previous
+                .services
+                .get(*name)
+                .is_none_or(|previous| previous.digest() != service.digest())
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    select(file, changed, false)
+}
+
+fn split(file: &Compose, format: &Format, directory: &Path, sort: bool) -> Result<()> {
+    let extension = match format {
+        Format::Yaml => "yaml",
+        Format::Json { .. } => "json",
+        Format::Toml => "toml",
+        Format::Template(_) => bail!("Templates can only be used with --images"),
+    };
+
+    fs::create_dir_all(directory)
+        .with_context(|| anyhow!("{} could not be created", directory.display()))?;
+
+    for name in file.services.keys() {
+        let mut service_file = file.clone();
+        select(&mut service_file, referenced_services(file, name), false)?;
+
+        write(
+            &service_file,
+            format,
+            Some(directory.join(format!("{name}.{extension}"))),
+            sort,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Lists a service along with the ones it cannot run without, i.e. those whose network stack or
+/// volumes it uses and those it links to
+fn referenced_services(file: &Compose, name: &str) -> Vec<String> {
+    let mut referenced = IndexSet::new();
+    let mut services = VecDeque::from([name]);
+
+    while let Some(name) = services.pop_front() {
+        if !referenced.insert(name) {
+            continue;
+        }
+
+        if let Some(service) = file.services.get(name) {
+            services.extend(
+                service
+                    .network_mode
+                    .as_deref()
+                    .and_then(|network_mode| network_mode.strip_prefix("service:")),
+            );
+            services.extend(
+                service
+                    .volumes_from
+                    .iter()
+                    .filter(|source| !source.starts_with("container:"))
+                    .chain(&service.links)
+                    .filter_map(|reference| reference.split(':').next()),
+            );
+        }
+    }
+
+    referenced.into_iter().map(ToOwned::to_owned).collect()
+}
+
+fn relativize(file: &mut Compose, directory: &Path) {
+    for service in file.services.values_mut() {
+        service.volumes = mem::take(&mut service.volumes)
+            .into_iter()
+            .map(|mut volume| {
+                if let ServiceVolumeType::Bind(source) = &mut volume.r#type {
+                    if let Ok(path) = source.strip_prefix(directory) {
+                        *source = if path.as_os_str().is_empty() {
+                            PathBuf::from(".")
+                        } else {
+                            Path::new(".").join(path)
+                        };
+                    }
+                }
+
+                volume
+            })
+            .collect();
+    }
+}
+
+fn sort_keys(value: &mut Value) {
+    match value {
+        Value::Mapping(values) => {
+            let mut entries = mem::take(values).into_iter().collect::<Vec<_>>();
+            entries.sort_by_cached_key(|(key, _)| {
+                key.as_str().map_or_else(
+                    || serde_yaml::to_string(key).unwrap_or_default(),
+                    ToString::to_string,
+                )
+            });
+
+            for (key, mut value) in entries {
+                sort_keys(&mut value);
+                values.insert(key, value);
+            }
+        }
+        Value::Sequence(values) => {
+            for value in values {
+                sort_keys(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn prepare(file: &mut Compose, args: &Args, config: &Config) -> Result<()> {
+    if !args.select.is_empty() {
+        select(file, args.select.clone(), args.with_deps)?;
+    }
+
+    if args.relative_paths {
+        relativize(file, &config.project_directory);
+    }
+
+    if args.anonymize {
+        anonymize(file, &args.anonymize_pattern)?;
+    }
+
+    if args.prefix_resources {
+        prefix_resources(file);
+    }
+
+    if args.strip_version {
+        file.version = None;
+    }
+
+    Ok(())
+}
+
+/// Renames the networks and volumes named after the project, leaving the external and explicitly
+/// named ones as they are
+fn prefix_resources(file: &mut Compose) {
+    let Some(project_name) = file.name.clone() else {
+        return;
+    };
+    let prefixed = |key: &String, name: Option<&String>, external: Option<bool>| {
+        name.filter(|name| {
+            !external.unwrap_or_default() && **name == format!("{project_name}_{key}")
+        })
+        .cloned()
+    };
+    let mut networks = IndexMap::new();
+    let mut volumes = IndexMap::new();
+
+    file.networks = mem::take(&mut file.networks)
+        .into_iter()
+        .map(
+            |(key, network)| match prefixed(&key, network.name.as_ref(), network.external) {
+                Some(name) => {
+                    networks.insert(key, name.clone());
+                    (name, network)
+                }
+                None => (key, network),
+            },
+        )
+        .collect();
+    file.volumes = mem::take(&mut file.volumes)
+        .into_iter()
+        .map(
+            |(key, volume)| match prefixed(&key, volume.name.as_ref(), volume.external) {
+                Some(name) => {
+                    volumes.insert(key, name.clone());
+                    (name, volume)
+                }
+                None => (key, volume),
+            },
+        )
+        .collect();
+
+    for service in file.services.values_mut() {
+        service.networks = mem::take(&mut service.networks)
+            .into_iter()
+            .map(|(key, network)| (networks.get(&key).cloned().unwrap_or(key), network))
+            .collect();
+        service.volumes = mem::take(&mut service.volumes)
+            .into_iter()
+            .map(|mut volume| {
+                if let ServiceVolumeType::Volume(Some(source)) = &mut volume.r#type {
+                    if let Some(name) = volumes.get(source) {
+                        source.clone_from(name);
+                    }
+                }
+
+                volume
+            })
+            .collect();
+    }
+}
+
+/// Redacts the values without changing the structure, so that the file can still be parsed
+fn anonymize(file: &mut Compose, patterns: &[String]) -> Result<()> {
+    // Patterns are matched case-insensitively against the whole name, "*" matching any characters
+    let pattern = ["*PASSWORD*", "*SECRET*", "*KEY*", "*TOKEN*"]
+        .into_iter()
+        .chain(patterns.iter().map(String::as_str))
+        .map(|pattern| pattern.split('*').map(regex::escape).join(".*"))
+        .join("|");
+    let pattern = Regex::new(&format!("(?i)^(?:{pattern})$"))?;
+
+    for service in file.services.values_mut() {
+        for (name, value) in &mut service.environment {
+            if value.is_some() && pattern.is_match(name) {
+                *value = Some(String::from("***"));
+            }
+        }
+    }
+
+    for secret in file.secrets.values_mut() {
+        if let Some(file) = &mut secret.file {
+            *file = PathBuf::from("***");
+        }
+
+        if let Some(content) = &mut secret.content {
+            *content = String::from("***");
+        }
+    }
+
+    Ok(())
+}
+
+fn list_dangling(file: &Compose) {
+    let dangling = compose::dangling(file);
+
+    for (kind, names) in [
+        ("Networks", &dangling.networks),
+        ("Volumes", &dangling.volumes),
+        ("Secrets", &dangling.secrets),
+    ] {
+        // The default network is always declared, whether the Compose files use it or not
+        let names = names
+            .iter()
+            .filter(|name| kind != "Networks" || *name != "default")
+            .collect::<Vec<_>>();
+
+        if !names.is_empty() {
+            println!("{kind}:");
+
+            for name in names {
+                println!("  {name}");
+            }
+        }
+    }
+}
+
+fn serialize<T>(file: &T, format: &Format, sort: bool) -> Result<String>
+where
+    T: Serialize,
+{
+    if sort {
+        let mut file = serde_yaml::to_value(file)?;
+        sort_keys(&mut file);
+
+        return serialize(&file, format, false);
+    }
+
+    let mut contents;
+
+    match format {
+        Format::Yaml => {
+            contents = serde_yaml::to_string(file)?;
+        }
+        Format::Json { compact } => {
+            contents = if *compact {
+                serde_json::to_string(file)?
+            } else {
+                serde_json::to_string_pretty(file)?
+            };
+            contents.push('\n');
+        }
+        Format::Toml => {
+            let Value::Mapping(values) = serde_yaml::to_value(file)? else {
+                bail!("The Compose file must be a mapping to be represented in TOML");
+            };
+
+            contents = toml_edit::Document::from(toml_table(values)?).to_string();
+        }
+        Format::Template(_) => bail!("Templates can only be used with --images"),
+    }
+
+    Ok(contents)
+}
+
+fn unified_diff(old_label: &str, new_label: &str, old: &str, new: &str) -> Result<String> {
+    const CONTEXT: usize = 3;
+
+    // The trailing newline would otherwise be compared as an empty last line
+    let lines = diff::lines(
+        old.strip_suffix('\n').unwrap_or(old),
+        new.strip_suffix('\n').unwrap_or(new),
+    );
+    let mut hunks = Vec::<(usize, usize)>::new();
+
+    for (i, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, diff::Result::Both(..)))
+    {
+        let (start, end) = (
+            i.saturating_sub(CONTEXT),
+            (i + CONTEXT + 1).min(lines.len()),
+        );
+
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    if hunks.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut output = format!(
+        "{}\n{}\n",
+        style(format!("--- {old_label}")).bold(),
+        style(format!("+++ {new_label}")).bold()
+    );
+    // Line numbers in a hunk header start at 1, or designate the preceding line when it is empty
+    let range = |start: usize, length: usize| {
+        format!("{},{length}", if length == 0 { start } else { start + 1 })
+    };
+
+    for (start, end) in hunks {
+        let (old_start, new_start) =
+            lines[..start]
+                .iter()
+                .fold((0, 0), |(old, new), line| match line {
+                    diff::Result::Left(_) => (old + 1, new),
+                    diff::Result::Right(_) => (old, new + 1),
+                    diff::Result::Both(..) => (old + 1, new + 1),
+                });
+        let hunk = &lines[start..end];
+        let old_length = hunk
+            .iter()
+            .filter(|line| !matches!(line, diff::Result::Right(_)))
+            .count();
+        let new_length = hunk
+            .iter()
+            .filter(|line| !matches!(line, diff::Result::Left(_)))
+            .count();
+
+        writeln!(
+            output,
+            "{}",
+            style(format!(
+                "@@ -{} +{} @@",
+                range(old_start, old_length),
+                range(new_start, new_length)
+            ))
+            .cyan()
+        )?;
+
+        for line in hunk {
+            match line {
+                diff::Result::Left(line) => writeln!(output, "{}", style(format!("-{line}")).red()),
+                diff::Result::Right(line) => {
+                    writeln!(output, "{}", style(format!("+{line}")).green())
+                }
+                diff::Result::Both(line, _) => writeln!(output, " {line}"),
+            }?;
+        }
+    }
+
+    Ok(output)
+}
+
+fn write<T>(file: &T, format: &Format, output: Option<PathBuf>, sort: bool) -> Result<()>
+where
+    T: Serialize,
+{
+    let contents = serialize(file, format, sort)?;
+
+    if let Some(path) = output {
+        fs::write(&path, contents).with_context(|| match path.absolutize() {
+            Ok(path) => anyhow!(
+                "{} not found",
+                path.parent().unwrap_or_else(|| Path::new("/")).display()
+            ),
+            Err(err) => Error::from(err),
+        })?;
+    } else {
+        print!("{contents}");
+    }
+
+    Ok(())
+}
+
+/// Returns the required variables, in the given order, that are unset or empty
+fn missing_variables<'a>(names: &'a [String], config: &Config) -> Vec<&'a str> {
+    let variables = compose::variables(config);
+
+    names
+        .iter()
+        .filter(|name| compose::lookup(&variables, name).map_or(true, |value| value.is_empty()))
+        .map(String::as_str)
+        .unique()
+        .collect()
+}
+
+pub(crate) async fn run(mut args: Args, config: &Config) -> Result<()> {
+    if args.compact {
+        match &mut args.format {
+            Format::Json { compact } => *compact = true,
+            _ => bail!("--compact can only be used with --format json"),
+        }
+    }
+
+    let missing = missing_variables(&args.require, config);
+
+    if !missing.is_empty() {
+        bail!(Failure::Interpolation.error(format_args!(
+            "Required variables are not set: {}",
+            missing.join(", ")
+        )));
+    }
+
+    let options = Options {
+        no_interpolate: args.no_interpolate || args.merge_only,
+        no_consistency: args.no_consistency,
+        unknown_properties: UnknownProperties::new(args.strict, args.ignore_unknown),
+        resolve_extends: args.resolve_extends,
+        no_prune: args.list_dangling,
+    };
+
+    if args.variables {
+        if !args.quiet {
+            for (name, default) in compose::referenced_names(config)?.into_iter().sorted() {
+                match default {
+                    Some(default) if args.with_defaults => println!("{name}={default}"),
+                    _ => println!("{name}"),
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    if args.no_normalize || args.merge_only {
+        let mut file = compose::load_unnormalized(config, &options)?.report();
+
+        if let Some(values) = file.as_mapping_mut().filter(|_| args.strip_version) {
+            values.remove("version");
+        }
+
+        if !args.quiet {
+            write(&file, &args.format, args.output, args.deterministic)?;
+        }
+
+        return Ok(());
+    }
+
+    let mut parsed = compose::parse(config, &options)?;
+    let sections = mem::take(&mut parsed.sections);
+    let mut file = parsed.report();
+
+    if args.list_dangling {
+        if !args.quiet {
+            list_dangling(&file);
+        }
+
+        return Ok(());
+    }
+
+    prepare(&mut file, &args, config)?;
+
+    if args.check_images_exist {
+        check_images_exist(&file, config).await?;
+    }
+
+    if args.resolve_image_digests {
+        resolve_image_digests(&mut file, config).await?;
+    }
+
+    if let Some(previous) = &args.since {
+        since(&mut file, previous)?;
+    }
+
+    if args.diff {
+        let Some((_, files)) = config
+            .files
+            .split_last()
+            .filter(|(_, files)| !files.is_empty())
+        else {
+            bail!("--diff needs at least two Compose files");
+        };
+        let base_config = Config {
+            files: files.to_vec(),
+            ..config.clone()
+        };
+        let mut base = compose::parse(&base_config, &options)?.report();
+        prepare(&mut base, &args, config)?;
+
+        if !args.quiet {
+            let label = |files: &[PathBuf]| files.iter().map(|file| file.display()).join(" + ");
+
+            print!(
+                "{}",
+                unified_diff(
+                    &label(files),
+                    &label(&config.files),
+                    &serialize(&base, &args.format, args.deterministic)?,
+                    &serialize(&file, &args.format, args.deterministic)?,
+                )?
+            );
+        }
+
+        return Ok(());
+    }
 
     if !args.quiet {
-        if args.services {
+        if args.print_resolved_env {
+            for (name, value) in compose::referenced_variables(config, &file)? {
+                let value = match value {
+                    Some(_)
+                        if !args.show_secrets
+                            && ["PASSWORD", "TOKEN", "SECRET"]
+                                .iter()
+                                .any(|secret| name.to_ascii_uppercase().contains(secret)) =>
+                    {
+                        String::from("********")
+                    }
+                    Some(value) => value,
+                    None => String::from("<unset>"),
+                };
+
+                println!("{name}={value}");
+            }
+        } else if args.services && args.table {
+            let rows = file
+                .services
+                .into_iter()
+                .map(|(name, service)| {
+                    [
+                        name,
+                        service.image.unwrap_or_default(),
+                        service
+                            .ports
+                            .iter()
+                            .find(|port| port.published.is_some())
+                            .map(ToString::to_string)
+                            .unwrap_or_default(),
+                        service.profiles.join(","),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let header = ["NAME", "IMAGE", "PORTS", "PROFILES"].map(String::from);
+            let widths = (0..header.len())
+                .map(|i| {
+                    iter::once(&header)
+                        .chain(&rows)
+                        .map(|row| row[i].chars().count())
+                        .max()
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<_>>();
+
+            for row in iter::once(&header).chain(&rows) {
+                println!(
+                    "{}",
+                    row.iter()
+                        .zip(&widths)
+                        .map(|(column, width)| format!("{column:width$}"))
+                        .join("  ")
+                        .trim_end()
+                );
+            }
+        } else if args.services {
             for service in file.services.into_keys() {
                 println!("{service}");
             }
@@ -75,39 +1120,241 @@ pub(crate) fn run(args: Args, config: &Config) -> Result<()> {
                 println!("{profile}");
             }
         } else if args.images {
-            for image in file
+            for (name, image) in file
                 .services
-                .into_values()
-                .filter_map(|service| service.image)
+                .into_iter()
+                .filter_map(|(name, service)| service.image.map(|image| (name, image)))
             {
-                println!("{image}");
-            }
-        } else {
-            let mut contents;
+                if let Format::Template(placeholders) = &args.format {
+                    for placeholder in placeholders {
+                        match placeholder {
+                            Placeholder::Text(text) => print!("{text}"),
+                            Placeholder::Name => print!("{name}"),
+                            Placeholder::Image => print!("{image}"),
+                        }
+                    }
 
-            match args.format {
-                Format::Yaml => {
-                    contents = serde_yaml::to_string(&file)?;
-                }
-                Format::Json => {
-                    contents = serde_json::to_string_pretty(&file)?;
-                    contents.push('\n');
+                    println!();
+                } else {
+                    println!("{image}");
                 }
             }
-
-            if let Some(path) = args.output {
-                fs::write(&path, contents).with_context(|| match path.absolutize() {
-                    Ok(path) => anyhow!(
-                        "{} not found",
-                        path.parent().unwrap_or_else(|| Path::new("/")).display()
-                    ),
-                    Err(err) => Error::from(err),
-                })?;
+        } else if let Some(service) = args.hash {
+            if service == "*" {
+                println!("{}", file.digest());
             } else {
-                print!("{contents}");
+                println!(
+                    "{}",
+                    file.services
+                        .get(&service)
+                        .ok_or_else(|| anyhow!("No such service: \"{service}\""))?
+                        .digest()
+                );
+            }
+        } else if let Some(directory) = args.output_dir {
+            split(&file, &args.format, &directory, args.deterministic)?;
+        } else if args.keep_empty {
+            let mut file = serde_yaml::to_value(&file)?;
+
+            if let Some(values) = file.as_mapping_mut() {
+                for section in ["networks", "volumes", "secrets"] {
+                    if sections.contains(section) && !values.contains_key(section) {
+                        values.insert(Value::from(section), Value::Mapping(Mapping::new()));
+                    }
+                }
             }
+
+            write(&file, &args.format, args.output, args.deterministic)?;
+        } else {
+            write(&file, &args.format, args.output, args.deterministic)?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, env, fs, path::PathBuf};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        compose::{self, types::Compose, Options},
+        config::Config,
+    };
+
+    #[test]
+    fn unified_diff() {
+        console::set_colors_enabled(false);
+
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+
+        assert_eq!(
+            super::unified_diff("old", "new", old, new).unwrap(),
+            "--- old\n+++ new\n@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n@@ -8,3 +8,4 @@\n h\n i\n j\n+k\n"
+        );
+        assert_eq!(super::unified_diff("old", "new", old, old).unwrap(), "");
+    }
+
+    #[test]
+    fn anonymize() {
+        let mut file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    environment:\n      DB_PASSWORD: hunter2\n      api_key: abc\n      AWS_CREDENTIALS: xyz\n      DEBUG: 1\n      TOKEN:\nsecrets:\n  cert:\n    file: /run/cert.pem\n  key:\n    content: abc\n",
+        )
+        .unwrap();
+        super::anonymize(&mut file, &[String::from("*_credentials")]).unwrap();
+
+        assert_eq!(
+            serde_yaml::to_value(&file.services["web"].environment).unwrap(),
+            serde_yaml::from_str::<serde_yaml::Value>(
+                "DB_PASSWORD: '***'\napi_key: '***'\nAWS_CREDENTIALS: '***'\nDEBUG: '1'\nTOKEN: null\n"
+            )
+            .unwrap()
+        );
+        assert_eq!(file.secrets["cert"].file, Some(PathBuf::from("***")));
+        assert_eq!(file.secrets["key"].content.as_deref(), Some("***"));
+    }
+
+    #[test]
+    fn prefix_resources() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from("name: app\nservices:\n  web:\n    image: busybox\n    networks: [default, front, shared]\n    volumes: [data:/data, cache:/cache]\nnetworks:\n  front:\n    name: frontend\n  shared:\n    external: true\nvolumes:\n  data:\n  cache:\n    name: cache\n"),
+        )];
+        let mut file = compose::parse_sources(&config, &sources, &Options::default())
+            .unwrap()
+            .file;
+        super::prefix_resources(&mut file);
+
+        assert_eq!(
+            file.networks.keys().collect::<Vec<_>>(),
+            ["front", "shared", "app_default"]
+        );
+        assert_eq!(
+            file.volumes.keys().collect::<Vec<_>>(),
+            ["app_data", "cache"]
+        );
+
+        let service = &file.services["web"];
+
+        assert_eq!(
+            service.networks.keys().collect::<Vec<_>>(),
+            ["app_default", "front", "shared"]
+        );
+        assert_eq!(
+            service
+                .volumes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["app_data:/data", "cache:/cache"]
+        );
+    }
+
+    #[test]
+    fn referenced_services() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    network_mode: service:proxy\n    volumes_from: [data:ro, container:backup]\n    links: [cache:redis]\n  proxy:\n    image: busybox\n    volumes_from: [web]\n  data:\n    image: busybox\n  cache:\n    image: busybox\n  other:\n    image: busybox\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            super::referenced_services(&file, "web"),
+            ["web", "proxy", "data", "cache"]
+        );
+        assert_eq!(
+            super::referenced_services(&file, "proxy"),
+            ["proxy", "web", "data", "cache"]
+        );
+        assert_eq!(super::referenced_services(&file, "other"), ["other"]);
+    }
+
+    #[test]
+    fn repository_digest() {
+        let digests = VecDeque::from([
+            String::from("quay.io/team/app@sha256:aaa"),
+            String::from("docker.io/library/app@sha256:bbb"),
+            String::from("localhost:5000/app@sha256:ccc"),
+        ]);
+        let digest = |image| super::repository_digest(image, digests.clone());
+
+        assert_eq!(
+            digest("app:1.0").as_deref(),
+            Some("docker.io/library/app@sha256:bbb")
+        );
+        assert_eq!(
+            digest("team/app").as_deref(),
+            Some("quay.io/team/app@sha256:aaa")
+        );
+        assert_eq!(
+            digest("quay.io/team/app:1.0").as_deref(),
+            Some("quay.io/team/app@sha256:aaa")
+        );
+        assert_eq!(
+            digest("localhost:5000/app").as_deref(),
+            Some("localhost:5000/app@sha256:ccc")
+        );
+        assert_eq!(digest("ghcr.io/team/app:1.0"), None);
+    }
+
+    #[test]
+    fn since() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let parse = |source: &str| {
+            let sources = [(PathBuf::from("compose.yaml"), String::from(source))];
+
+            compose::parse_sources(&config, &sources, &Options::default())
+                .unwrap()
+                .file
+        };
+        let previous = parse("name: app\nservices:\n  web:\n    image: nginx\n  db:\n    image: postgres:15\n  cache:\n    image: redis\n");
+        let path = env::temp_dir().join(format!("haddock-since-{}.yaml", std::process::id()));
+        fs::write(&path, serde_yaml::to_string(&previous).unwrap()).unwrap();
+
+        let mut file = parse("name: app\nservices:\n  web:\n    image: nginx\n    depends_on: [db]\n  db:\n    image: postgres:16\n  worker:\n    image: busybox\n");
+        let result = super::since(&mut file, &path);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        assert_eq!(
+            file.services.keys().collect::<Vec<_>>(),
+            ["web", "db", "worker"]
+        );
+
+        let mut file = parse(
+            "name: app\nservices:\n  web:\n    image: nginx\n  db:\n    image: postgres:16\n",
+        );
+        fs::write(&path, serde_yaml::to_string(&previous).unwrap()).unwrap();
+        let result = super::since(&mut file, &path);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        assert_eq!(file.services.keys().collect::<Vec<_>>(), ["db"]);
+    }
+
+    #[test]
+    fn missing_variables() {
+        let config = Config {
+            variables: [("TAG", "latest"), ("EMPTY", "")]
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect(),
+            ..Config::default()
+        };
+        let names = ["TAG", "EMPTY", "HADDOCK_TEST_UNSET", "EMPTY"].map(ToOwned::to_owned);
+
+        assert_eq!(
+            super::missing_variables(&names, &config),
+            ["EMPTY", "HADDOCK_TEST_UNSET"]
+        );
+    }
+}
@@ -52,14 +52,12 @@ pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<(
                         .source
                         .0
                         .as_ref()
-                        .map(|source| *source == service)
-                        .unwrap_or_default()
+                        .is_some_and(|source| *source == service)
                         || args
                             .destination
                             .0
                             .as_ref()
-                            .map(|destination| *destination == service)
-                            .unwrap_or_default()
+                            .is_some_and(|destination| *destination == service)
                     {
                         container
                             .names
@@ -85,7 +85,11 @@ async fn create_pod(
             ),
             (
                 "project.environment-file",
-                config.env_file.to_string_lossy().as_ref(),
+                &config
+                    .env_files
+                    .iter()
+                    .map(|file| file.to_string_lossy())
+                    .join(","),
             ),
             ("config-hash", &file.digest()),
         ]
@@ -217,16 +221,20 @@ async fn create_secrets(
                     .map(|label| format!("io.podman.compose.{}={}", label.0, label.1))
                     .collect::<Vec<_>>();
 
-                podman
-                    .run(
-                        ["secret", "create"]
-                            .into_iter()
-                            .chain(labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(secret_labels.iter().flat_map(|label| ["--label", label]))
-                            .chain(secret.to_args().iter().map(AsRef::as_ref)),
-                    )
-                    .await
-                    .finish_with_message(spinner, "Created")?;
+                let args = ["secret", "create"]
+                    .into_iter()
+                    .chain(labels.iter().flat_map(|label| ["--label", label]))
+                    .chain(secret_labels.iter().flat_map(|label| ["--label", label]))
+                    .map(String::from)
+                    .chain(secret.to_args())
+                    .collect::<Vec<_>>();
+
+                if let Some(content) = &secret.content {
+                    podman.run_with_input(args, content).await
+                } else {
+                    podman.run(args).await
+                }
+                .finish_with_message(spinner, "Created")?;
             } else {
                 spinner.finish_with_message("Exists");
             }
@@ -538,8 +546,7 @@ pub(crate) async fn run(
     if args.force_recreate
         || (!args.no_recreate
             && config_hash
-                .map(|config_hash| config_hash != file.digest())
-                .unwrap_or_default())
+                .is_some_and(|config_hash| config_hash != file.digest()))
     {
         down::run(
             down::Args {
@@ -64,12 +64,10 @@ pub(crate) async fn run(args: Args, podman: &Podman, file: &Compose) -> Result<(
             container.labels.and_then(|labels| {
                 if labels
                     .service
-                    .map(|service| args.service == service)
-                    .unwrap_or_default()
+                    .is_some_and(|service| args.service == service)
                     && labels
                         .container_number
-                        .map(|n| n == args.index)
-                        .unwrap_or_default()
+                        .is_some_and(|n| n == args.index)
                 {
                     container.names.pop_front()
                 } else {
@@ -0,0 +1,70 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Error, Result};
+use path_absolutize::Absolutize;
+use schemars::{generate::SchemaSettings, Schema};
+
+use crate::compose::types::Compose;
+
+/// Print the JSON Schema of the Compose files that are accepted
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    /// Save to file (default to stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Generates the schema with every definition inlined, as the names given to the `serde_with`
+/// adapters are not all valid references
+pub(crate) fn schema() -> Schema {
+    SchemaSettings::default()
+        .with(|settings| settings.inline_subschemas = true)
+        .into_generator()
+        .into_root_schema_for::<Compose>()
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    let contents = serde_json::to_string_pretty(&schema())?;
+
+    if let Some(path) = args.output {
+        fs::write(&path, format!("{contents}\n")).with_context(|| match path.absolutize() {
+            Ok(path) => anyhow!(
+                "{} not found",
+                path.parent().unwrap_or_else(|| Path::new("/")).display()
+            ),
+            Err(err) => Error::from(err),
+        })?;
+    } else {
+        println!("{contents}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn schema() {
+        let schema = super::schema().to_value();
+        let service = &schema["properties"]["services"]["additionalProperties"]["properties"];
+
+        assert!(schema.get("$defs").is_none());
+        assert_eq!(
+            service["command"]["anyOf"],
+            json!([
+                { "type": "array", "items": { "type": "string" } },
+                { "allOf": [{ "type": "string" }], "writeOnly": true }
+            ])
+        );
+        assert_eq!(
+            service["mem_limit"]["type"],
+            json!(["integer", "string", "null"])
+        );
+    }
+}
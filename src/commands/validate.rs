@@ -0,0 +1,42 @@
+use anyhow::{bail, Result};
+
+use crate::{
+    compose::{self, Options, UnknownProperties},
+    config::Config,
+    utils::{self, Failure},
+};
+
+/// Validates the Compose file, reporting every problem found
+#[derive(clap::Args, Debug)]
+#[command(next_display_order = None)]
+pub(crate) struct Args {
+    /// Fail on unsupported/unknown properties
+    #[arg(long, conflicts_with = "ignore_unknown")]
+    strict: bool,
+
+    /// Don't warn about unsupported/unknown properties
+    #[arg(long)]
+    ignore_unknown: bool,
+}
+
+pub(crate) fn run(args: Args, config: &Config) -> Result<()> {
+    let file = compose::load(
+        config,
+        &Options {
+            unknown_properties: UnknownProperties::new(args.strict, args.ignore_unknown),
+            ..Options::default()
+        },
+    )?
+    .report();
+    let errors = compose::validate(&file);
+
+    for err in &errors {
+        utils::error(err);
+    }
+
+    match errors.len() {
+        0 => Ok(()),
+        1 => bail!(Failure::Specification.error("1 error found")),
+        n => bail!(Failure::Specification.error(format_args!("{n} errors found"))),
+    }
+}
@@ -1,35 +1,163 @@
 automod::dir!(pub(crate) "src/compose");
 
 use std::{
+    borrow::Cow,
     env::{self, VarError},
+    fmt::{self, Formatter},
     fs,
     io::{self, Read},
+    mem,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Context, Error, Result};
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
+use path_absolutize::Absolutize;
 use petgraph::{algo::tarjan_scc, graphmap::DiGraphMap};
-use serde_yaml::Value;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_with::formats::Separator;
+use serde_yaml::{Location, Mapping, Value};
 
 use self::{
-    parser::{State, Token, Var},
-    types::{Compose, Condition, ServiceVolumeType},
+    parser::{Case, Occurrence, State, Token, Var},
+    types::{
+        BlkioConfig, Compose, Condition, Dependency, DeployConfig, Development, Extends, Healthcheck,
+        Include, IpamConfig, Logging, Network, Port, Resource, ResourceLimit, Resources, Secret,
+        Service, ServiceVolumeType, SwapLimit, Volume, WatchAction, WatchRule,
+    },
 };
 use crate::{
-    config::Config,
-    utils::{regex, STYLED_WARNING},
+    config::{self, Config},
+    utils::{self, regex, warning, Failure, Logged, PathSeparator},
 };
 
-fn evaluate(tokens: Vec<Token>) -> Result<String> {
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const FETCH_LIMIT: u64 = 10 * 1024 * 1024;
+
+// Architectures that images are commonly published for, others being warned about
+const ARCHITECTURES: [&str; 10] = [
+    "386", "amd64", "arm", "arm64", "mips64le", "ppc64le", "riscv64", "s390x", "loong64", "wasm",
+];
+
+#[derive(Default, Clone, Copy, Debug)]
+pub(crate) enum UnknownProperties {
+    #[default]
+    Warn,
+    Ignore,
+    Deny,
+}
+
+impl UnknownProperties {
+    pub(crate) fn new(strict: bool, ignore_unknown: bool) -> Self {
+        if strict {
+            UnknownProperties::Deny
+        } else if ignore_unknown {
+            UnknownProperties::Ignore
+        } else {
+            UnknownProperties::Warn
+        }
+    }
+}
+
+/// A warning raised while loading the Compose files, left to the caller to report
+pub(crate) type Warning = Logged;
+
+/// The result of loading the Compose files, along with the warnings raised doing so
+#[derive(Debug)]
+pub(crate) struct Parsed<T> {
+    pub(crate) file: T,
+    pub(crate) warnings: Vec<Warning>,
+    /// The top-level sections declared in the files, including the empty ones the file drops
+    pub(crate) sections: IndexSet<String>,
+}
+
+impl<T> Parsed<T> {
+    /// Prints the warnings like the rest of the CLI does, returning the file
+    pub(crate) fn report(self) -> T {
+        for warning in self.warnings {
+            utils::warn_logged(warning);
+        }
+
+        self.file
+    }
+}
+
+// The warnings raised before an error are printed right away, the caller only getting the error
+fn collect<T>(config: &Config, f: impl FnOnce() -> Result<T>) -> Result<Parsed<T>> {
+    let (result, warnings) = utils::collect_warnings(f);
+
+    match result {
+        Ok(_) if config.warn_as_error && !warnings.is_empty() => {
+            for warning in &warnings {
+                utils::error_logged(warning);
+            }
+
+            match warnings.len() {
+                1 => bail!("1 warning treated as an error"),
+                n => bail!("{n} warnings treated as errors"),
+            }
+        }
+        Ok(file) => Ok(Parsed {
+            file,
+            warnings,
+            sections: IndexSet::new(),
+        }),
+        Err(err) => {
+            for warning in warnings {
+                utils::warn_logged(warning);
+            }
+
+            Err(err)
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct Options {
+    pub(crate) no_interpolate: bool,
+    pub(crate) no_consistency: bool,
+    pub(crate) unknown_properties: UnknownProperties,
+    pub(crate) resolve_extends: bool,
+    pub(crate) no_prune: bool,
+}
+
+fn substring(value: &str, offset: isize, length: Option<isize>) -> String {
+    let chars = value.chars().collect::<Vec<_>>();
+    let start = if offset < 0 {
+        chars.len().saturating_sub(offset.unsigned_abs())
+    } else {
+        offset.unsigned_abs().min(chars.len())
+    };
+    let end = match length {
+        Some(length) if length < 0 => chars.len().saturating_sub(length.unsigned_abs()),
+        Some(length) => start.saturating_add(length.unsigned_abs()).min(chars.len()),
+        None => chars.len(),
+    };
+
+    chars[start..end.max(start)].iter().collect()
+}
+
+pub(crate) fn lookup(variables: &IndexMap<String, String>, name: &str) -> Result<String, VarError> {
+    variables.get(name).cloned().ok_or(VarError::NotPresent)
+}
+
+/// Evaluates the tokens of a string, `property` being the file and the path of the property it
+/// was found at, if any, for the warnings about unset variables
+fn evaluate(
+    tokens: Vec<Token>,
+    variables: &IndexMap<String, String>,
+    property: Option<(&Path, &str)>,
+) -> Result<String> {
     tokens
         .into_iter()
         .map(|token| match token {
             Token::Str(string) => Ok(string),
             Token::Var(name, var) => match var {
                 Some(Var::Default(state, tokens)) => match state {
-                    State::Set => env::var(name),
-                    State::SetAndNonEmpty => env::var(name).and_then(|var| {
+                    State::Set => lookup(variables, &name),
+                    State::SetAndNonEmpty => lookup(variables, &name).and_then(|var| {
                         if var.is_empty() {
                             Err(VarError::NotPresent)
                         } else {
@@ -37,10 +165,10 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
                         }
                     }),
                 }
-                .or_else(|_| evaluate(tokens)),
+                .or_else(|_| evaluate(tokens, variables, property)),
                 Some(Var::Err(state, tokens)) => match state {
-                    State::Set => env::var(&name),
-                    State::SetAndNonEmpty => env::var(&name).and_then(|var| {
+                    State::Set => lookup(variables, &name),
+                    State::SetAndNonEmpty => lookup(variables, &name).and_then(|var| {
                         if var.is_empty() {
                             Err(VarError::NotPresent)
                         } else {
@@ -49,7 +177,7 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
                     }),
                 }
                 .or_else(|_| {
-                    evaluate(tokens).and_then(|err| {
+                    evaluate(tokens, variables, property).and_then(|err| {
                         if err.is_empty() {
                             bail!("Required variable \"{name}\" is missing a value");
                         }
@@ -58,8 +186,8 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
                     })
                 }),
                 Some(Var::Replace(state, tokens)) => match state {
-                    State::Set => env::var(name),
-                    State::SetAndNonEmpty => env::var(name).and_then(|var| {
+                    State::Set => lookup(variables, &name),
+                    State::SetAndNonEmpty => lookup(variables, &name).and_then(|var| {
                         if var.is_empty() {
                             Err(VarError::NotPresent)
                         } else {
@@ -67,12 +195,60 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
                         }
                     }),
                 }
-                .map_or_else(|_| Ok(String::new()), |_| evaluate(tokens)),
-                None => Ok(env::var(&name).unwrap_or_else(|_| {
-                    eprintln!(
-                        "{} The \"{name}\" variable is not set, defaulting to a blank string",
-                        *STYLED_WARNING
-                    );
+                .map_or_else(|_| Ok(String::new()), |_| evaluate(tokens, variables, property)),
+                Some(Var::Substring(offset, length)) => {
+                    let offset = evaluate(offset, variables, property)?;
+                    let offset = offset.trim().parse().with_context(|| {
+                        anyhow!("Invalid substring offset \"{offset}\" for variable \"{name}\"")
+                    })?;
+                    let length = length
+                        .map(|length| {
+                            let length = evaluate(length, variables, property)?;
+
+                            length.trim().parse().with_context(|| {
+                                anyhow!("Invalid substring length \"{length}\" for variable \"{name}\"")
+                            })
+                        })
+                        .transpose()?;
+
+                    Ok(substring(
+                        &lookup(variables, &name).unwrap_or_default(),
+                        offset,
+                        length,
+                    ))
+                }
+                Some(Var::Pattern(occurrence, pattern, replacement)) => {
+                    let value = lookup(variables, &name).unwrap_or_default();
+                    let pattern = evaluate(pattern, variables, property)?;
+                    let replacement = evaluate(replacement, variables, property)?;
+
+                    Ok(match occurrence {
+                        Occurrence::First => value.replacen(&pattern, &replacement, 1),
+                        Occurrence::All => value.replace(&pattern, &replacement),
+                    })
+                }
+                Some(Var::Case(case)) => {
+                    let value = lookup(variables, &name).unwrap_or_default();
+
+                    Ok(match case {
+                        Case::Upper => value.to_uppercase(),
+                        Case::Lower => value.to_lowercase(),
+                    })
+                }
+                None => Ok(lookup(variables, &name).unwrap_or_else(|_| {
+                    if let Some((file, path)) = property {
+                        utils::warn_logged(Logged {
+                            message: format!(
+                                "{path}: \"${name}\" is not set, defaulting to a blank string"
+                            ),
+                            file: Some(display_path(file).into_owned()),
+                            path: Some(path.to_owned()),
+                        });
+                    } else {
+                        warning!(
+                            "The \"{name}\" variable is not set, defaulting to a blank string"
+                        );
+                    }
 
                     String::new()
                 })),
@@ -82,441 +258,3576 @@ fn evaluate(tokens: Vec<Token>) -> Result<String> {
         .map(|tokens| tokens.join(""))
 }
 
-fn interpolate(value: &Value) -> Result<Value> {
-    if let Some(value) = value.as_str() {
-        parser::parse(value).and_then(evaluate).map(Value::String)
-    } else if let Some(values) = value.as_sequence() {
-        values.iter().map(interpolate).collect()
-    } else if let Some(values) = value.as_mapping() {
-        values
-            .iter()
-            .map(|(key, value)| {
-                interpolate(value)
-                    .with_context(|| key.as_str().unwrap().to_string())
-                    .map(|value| (key.clone(), value))
-            })
-            .collect::<Result<_>>()
-            .map(Value::Mapping)
-    } else {
-        Ok(value.clone())
+fn merge_keys(value: &mut Value) -> Result<()> {
+    match value {
+        Value::Mapping(mapping) => {
+            for value in mapping.values_mut() {
+                merge_keys(value)?;
+            }
+
+            if let Some(merge) = mapping.remove("<<") {
+                let merges = match merge {
+                    Value::Mapping(merge) => vec![merge],
+                    Value::Sequence(merges) => merges
+                        .into_iter()
+                        .map(|merge| match merge {
+                            Value::Mapping(merge) => Ok(merge),
+                            _ => Err(anyhow!("Merge key \"<<\" only accepts mappings")),
+                        })
+                        .collect::<Result<_>>()?,
+                    _ => bail!("Merge key \"<<\" only accepts a mapping or a sequence of mappings"),
+                };
+
+                for merge in merges {
+                    for (key, value) in merge {
+                        mapping.entry(key).or_insert(value);
+                    }
+                }
+            }
+        }
+        Value::Sequence(values) => {
+            for value in values {
+                merge_keys(value)?;
+            }
+        }
+        Value::Tagged(tagged) => merge_keys(&mut tagged.value)?,
+        _ => {}
     }
+
+    Ok(())
 }
 
-pub(crate) fn parse(config: &Config, no_interpolate: bool) -> Result<Compose> {
-    let contents = config
-        .files
-        .iter()
-        .map(|path| {
-            if path.as_os_str() == "-" {
-                let mut content = String::new();
-                let mut stdin = io::stdin();
+fn parse_yaml(source: &str) -> Result<Value> {
+    let mut value = serde_yaml::from_str(source)?;
+    merge_keys(&mut value)?;
 
-                stdin.read_to_string(&mut content)?;
+    Ok(value)
+}
 
-                Ok((path, content))
-            } else {
-                fs::read_to_string(path)
-                    .with_context(|| format!("{} not found", path.display()))
-                    .map(|content| (path, content))
-            }
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    let files = contents
-        .into_iter()
-        .enumerate()
-        .map(|(i, (path, content))| {
-            serde_yaml::from_str(&content)
-                .map_err(Error::from)
-                .map(|mut content: Value| {
-                    if let Some(values) = content.as_mapping_mut() {
-                        let name = if config.project_name.is_some() {
-                            config.project_name.clone()
-                        } else if let Some((_, n)) =
-                            values.into_iter().find(|(key, _)| *key == "name")
-                        {
-                            n.as_str()
-                                .map(ToString::to_string)
-                                .or_else(|| n.as_bool().map(|n| n.to_string()))
-                                .or_else(|| n.as_u64().map(|n| n.to_string()))
-                                .or_else(|| n.as_i64().map(|n| n.to_string()))
-                                .or_else(|| n.as_f64().map(|n| n.to_string()))
-                                .or_else(|| Some(String::new()))
-                        } else if i == 0 {
-                            Some(String::new())
-                        } else {
-                            None
-                        };
+/// Removes the `!reset` and `!override` tags, returning the paths of the tagged properties that must
+/// be discarded from the previous files before merging
+fn strip_tags(value: &mut Value, path: &mut Vec<String>, resets: &mut Vec<Vec<String>>) {
+    if let Value::Mapping(mapping) = value {
+        let mut reset = Vec::new();
 
-                        if let Some(mut name) = name {
-                            let re = regex!(r"^[^a-zA-Z0-9]+|[^a-zA-Z0-9_.-]");
-                            name = re.replace_all(&name, "").to_ascii_lowercase();
-
-                            if name.is_empty() {
-                                name = re
-                                    .replace_all(
-                                        &env::current_dir()
-                                            .ok()
-                                            .and_then(|name| {
-                                                name.file_name()
-                                                    .map(|name| name.to_string_lossy().to_string())
-                                            })
-                                            .unwrap_or_default(),
-                                        "",
-                                    )
-                                    .to_ascii_lowercase();
-                            }
-
-                            env::set_var("COMPOSE_PROJECT_NAME", &name);
-                            values.insert(Value::String(String::from("name")), Value::String(name));
-                        }
-                    }
+        for (key, value) in mapping.iter_mut() {
+            let Some(key) = key.as_str() else {
+                continue;
+            };
 
-                    (path, content)
-                })
-        })
-        .map(|content| {
-            if no_interpolate {
-                content
-            } else {
-                content.and_then(|(path, content)| {
-                    interpolate(&content)
-                        .map_err(|err| match err.chain().collect::<Vec<_>>().split_last() {
-                            Some((err, props)) => {
-                                anyhow!("{}: {err}", props.iter().join("."))
-                            }
-                            None => err,
-                        })
-                        .map(|content| (path, content))
-                })
+            path.push(key.to_string());
+
+            if let Value::Tagged(tagged) = value {
+                if tagged.tag == "reset" {
+                    reset.push(key.to_string());
+                    resets.push(path.clone());
+                } else if tagged.tag == "override" {
+                    *value = mem::take(&mut tagged.value);
+                    resets.push(path.clone());
+                }
             }
-        })
-        .map(|content| {
-            content.and_then(|(path, content)| {
-                serde_yaml::to_string(&content)
-                    .map_err(Error::from)
-                    .map(|content| (path, content))
-            })
-        })
-        .map(|content| {
-            content.and_then(|(path, content)| {
-                let mut unused = IndexSet::new();
 
-                serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&content), |path| {
-                    unused.insert(path.to_string());
-                })
-                .with_context(|| {
-                    format!(
-                        "{} does not follow the Compose specification",
-                        path.display()
-                    )
-                })
-                .map(|file: Compose| (path, file, unused))
-            })
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-    let mut combined_file = Compose::new();
+            strip_tags(value, path, resets);
+            path.pop();
+        }
 
-    for (path, file, unused) in files {
-        if !unused.is_empty() {
-            eprintln!(
-                "{} Unsupported/unknown properties in {}: {}",
-                *STYLED_WARNING,
-                path.display(),
-                unused.into_iter().join(", ")
-            );
+        for key in reset {
+            mapping.remove(key);
+        }
+    }
+}
+
+fn remove(value: &mut Value, path: &[String]) {
+    if let (Value::Mapping(mapping), Some((key, path))) = (value, path.split_first()) {
+        if path.is_empty() {
+            mapping.remove(key.as_str());
+        } else if let Some(value) = mapping.get_mut(key.as_str()) {
+            remove(value, path);
         }
+    }
+}
 
-        combined_file.merge(file);
+fn parse_source(path: &Path, source: &str) -> Result<Value> {
+    if path.extension().is_some_and(|extension| extension == "json") {
+        serde_json::from_str(source).with_context(|| {
+            Failure::Syntax.error(format_args!("{} is not valid JSON", display_path(path)))
+        })
+    } else {
+        parse_yaml(source).with_context(|| {
+            Failure::Syntax.error(format_args!("{} is not valid YAML", display_path(path)))
+        })
     }
+}
 
-    combined_file.services.retain(|_, service| {
-        if service.profiles.is_empty() {
-            return true;
+pub(crate) fn interpolate_str(value: &str, variables: &IndexMap<String, String>) -> Result<String> {
+    parser::parse(value).and_then(|tokens| evaluate(tokens, variables, None))
+}
+
+fn references(tokens: Vec<Token>, names: &mut IndexMap<String, Option<String>>) {
+    for token in tokens {
+        if let Token::Var(name, var) = token {
+            let default = names.entry(name).or_default();
+
+            match var {
+                Some(Var::Default(_, tokens)) => {
+                    if default.is_none() {
+                        *default = tokens
+                            .iter()
+                            .map(|token| match token {
+                                Token::Str(string) => Some(string.as_str()),
+                                Token::Var(..) => None,
+                            })
+                            .collect::<Option<String>>();
+                    }
+
+                    references(tokens, names);
+                }
+                Some(Var::Err(_, tokens) | Var::Replace(_, tokens)) => references(tokens, names),
+                Some(Var::Substring(offset, length)) => {
+                    references(offset, names);
+                    references(length.unwrap_or_default(), names);
+                }
+                Some(Var::Pattern(_, pattern, replacement)) => {
+                    references(pattern, names);
+                    references(replacement, names);
+                }
+                Some(Var::Case(_)) | None => {}
+            }
         }
+    }
+}
 
-        for profile in &service.profiles {
-            if config.profiles.contains(profile) {
-                return true;
+fn referenced(value: &Value, names: &mut IndexMap<String, Option<String>>) {
+    match value {
+        Value::String(value) => {
+            if let Ok(tokens) = parser::parse(value) {
+                references(tokens, names);
+            }
+        }
+        Value::Sequence(values) => {
+            for value in values {
+                referenced(value, names);
             }
         }
+        Value::Mapping(values) => {
+            for (key, value) in values {
+                referenced(key, names);
+                referenced(value, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn interpolate(
+    file: &Path,
+    value: &Value,
+    variables: &IndexMap<String, String>,
+    props: &mut Vec<String>,
+) -> Result<Value> {
+    if let Some(value) = value.as_str() {
+        parser::parse(value)
+            .and_then(|tokens| evaluate(tokens, variables, Some((file, &props.join(".")))))
+            .map(Value::String)
+    } else if let Some(values) = value.as_sequence() {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                props.push(i.to_string());
+                let value = interpolate(file, value, variables, props)?;
+                props.pop();
 
-        false
-    });
+                Ok(value)
+            })
+            .collect()
+    } else if let Some(values) = value.as_mapping() {
+        let mut mapping = Mapping::new();
 
-    let all_networks = combined_file
-        .services
-        .values()
-        .flat_map(|service| service.networks.keys())
-        .collect::<IndexSet<_>>();
-    let all_volumes = combined_file
-        .services
-        .values()
-        .flat_map(|service| {
-            service
-                .volumes
-                .iter()
-                .filter_map(|volume| match &volume.r#type {
-                    ServiceVolumeType::Volume(source) => source.as_ref(),
-                    _ => None,
-                })
-        })
-        .collect::<IndexSet<_>>();
-    let all_secrets = combined_file
-        .services
-        .values()
-        .flat_map(|service| service.secrets.iter().map(|secret| &secret.source))
-        .collect::<IndexSet<_>>();
+        for (key, value) in values {
+            props.push(key.as_str().map_or_else(
+                || serde_yaml::to_string(key).unwrap_or_default().trim_end().to_owned(),
+                ToOwned::to_owned,
+            ));
 
-    combined_file
-        .networks
-        .entry(String::from("default"))
-        .or_default();
-    combined_file
-        .networks
-        .retain(|network, _| all_networks.contains(network));
+            let key = match key.as_str() {
+                Some(key) => Value::String(parser::parse(key).and_then(|tokens| {
+                    evaluate(tokens, variables, Some((file, &props.join("."))))
+                })?),
+                None => key.clone(),
+            };
 
-    for (name, network) in &mut combined_file.networks {
-        network.name.get_or_insert_with(|| {
-            match (network.external.unwrap_or_default(), &combined_file.name) {
-                (false, Some(project_name)) => format!("{project_name}_{name}"),
-                _ => name.clone(),
+            if mapping.contains_key(&key) {
+                bail!(
+                    "Key \"{}\" is defined more than once after interpolation",
+                    key.as_str().unwrap_or_default()
+                );
             }
-        });
+
+            let value = interpolate(file, value, variables, props)?;
+            props.pop();
+            mapping.insert(key, value);
+        }
+
+        Ok(Value::Mapping(mapping))
+    } else {
+        Ok(value.clone())
     }
+}
 
-    combined_file
-        .volumes
-        .retain(|volume, _| all_volumes.contains(volume));
+struct Locate<'a>(&'a [String]);
 
-    for (name, volume) in &mut combined_file.volumes {
-        volume.name.get_or_insert_with(|| {
-            match (volume.external.unwrap_or_default(), &combined_file.name) {
-                (false, Some(project_name)) => format!("{project_name}_{name}"),
-                _ => name.clone(),
-            }
-        });
+impl Locate<'_> {
+    fn found<E>(&self) -> Result<(), E>
+    where
+        E: de::Error,
+    {
+        if self.0.is_empty() {
+            Err(E::custom("found"))
+        } else {
+            Ok(())
+        }
     }
+}
 
-    combined_file
-        .secrets
-        .retain(|secrets, _| all_secrets.contains(secrets));
+impl<'de> DeserializeSeed<'de> for Locate<'_> {
+    type Value = ();
 
-    for (name, secret) in &mut combined_file.secrets {
-        secret.name.get_or_insert_with(|| {
-            match (secret.external.unwrap_or_default(), &combined_file.name) {
-                (false, Some(project_name)) => format!("{project_name}_{name}"),
-                _ => name.clone(),
-            }
-        });
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
     }
+}
 
-    for (name, service) in &combined_file.services {
-        if service.scale.is_some() {
-            eprintln!(
-                "{} `scale` is deprecated, use the `deploy.replicas` element instead",
-                *STYLED_WARNING
-            );
-        }
+impl<'de> Visitor<'de> for Locate<'_> {
+    type Value = ();
 
-        if service.mem_limit.is_some() {
-            eprintln!(
-                "{} `mem_limit` is deprecated, use the `deploy.limits.memory` element instead",
-                *STYLED_WARNING
-            );
-        }
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        formatter.write_str("any value")
+    }
 
-        if service.cpus.is_some() {
-            eprintln!(
-                "{} `cpus` is deprecated, use the `deploy.reservations.cpus` element instead",
-                *STYLED_WARNING
-            );
-        }
+    fn visit_bool<E>(self, _: bool) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.found()
+    }
 
-        if service.mem_reservation.is_some() {
-            eprintln!(
-                "{} `mem_reservation` is deprecated, use the `deploy.reservations.memory` element instead",
-                *STYLED_WARNING
-            );
-        }
+    fn visit_i64<E>(self, _: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.found()
+    }
 
-        if service.pids_limit.is_some() {
-            eprintln!(
-                "{} `pids_limit` is deprecated, use the `deploy.reservations.pids` element instead",
-                *STYLED_WARNING
-            );
-        }
+    fn visit_u64<E>(self, _: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.found()
+    }
 
-        if service
-            .depends_on
-            .values()
-            .any(|dependency| dependency.condition != Condition::Started)
-        {
-            eprintln!(
-                "{} \"service_healthy\" and \"service_completed_successfully\" are unsupported and will degrade to \"service_started\"",
-                *STYLED_WARNING
-            );
-        }
+    fn visit_f64<E>(self, _: f64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.found()
+    }
 
-        if service.image.is_none() {
-            bail!("Service \"{name}\" does not have an image specified");
-        }
+    fn visit_str<E>(self, _: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.found()
+    }
 
-        if service.network_mode.is_some() && service.networks.keys().any(|key| key != "default") {
-            bail!("Service \"{name}\" cannot have networks due to the network mode set");
-        }
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        self.found()
+    }
 
-        if service.network_mode.as_deref().unwrap_or_default() == "host"
-            && !service.ports.is_empty()
-        {
-            bail!("Service \"{name}\" cannot have port mappings due to host network mode");
-        }
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let Some((index, path)) = self.0.split_first() else {
+            return Err(de::Error::custom("found"));
+        };
+        let index = index.parse::<usize>().ok();
+        let mut i = 0;
 
-        if service.container_name.is_some()
-            && service
-                .deploy
-                .as_ref()
-                .and_then(|deploy| deploy.replicas)
-                .or(service.scale)
-                .unwrap_or(1)
-                > 1
-        {
-            bail!(
-                "Service \"{name}\" cannot scale beyond one container as it has a container name"
-            );
+        while if Some(i) == index {
+            seq.next_element_seed(Locate(path))?.is_some()
+        } else {
+            seq.next_element::<IgnoredAny>()?.is_some()
+        } {
+            i += 1;
         }
 
-        for label in service.labels.keys() {
-            if label.starts_with("io.podman.compose") {
-                bail!("Service \"name\" cannot have labels starting with \"io.podman.compose\"");
+        Ok(())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let Some((key, path)) = self.0.split_first() else {
+            return Err(de::Error::custom("found"));
+        };
+
+        while let Some(k) = map.next_key::<Value>()? {
+            if k.as_str() == Some(key) {
+                map.next_value_seed(Locate(path))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
             }
         }
 
-        for dependency in service.depends_on.keys() {
-            if !combined_file.services.contains_key(dependency) {
-                bail!("Service \"{name}\" depends on undefined service \"{dependency}\"");
-            }
-        }
+        Ok(())
+    }
+}
+
+fn locate(contents: &str, path: &[String]) -> Option<Location> {
+    Locate(path)
+        .deserialize(serde_yaml::Deserializer::from_str(contents))
+        .err()
+        .and_then(|err| err.location())
+}
+
+fn display_path(path: &Path) -> Cow<'_, str> {
+    if path.as_os_str() == "-" {
+        Cow::Borrowed("<stdin>")
+    } else {
+        path.to_string_lossy()
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    if path.as_os_str() == "-" {
+        let mut content = String::new();
+        let mut stdin = io::stdin();
+
+        stdin
+            .read_to_string(&mut content)
+            .context("<stdin> could not be read")?;
+
+        Ok(content)
+    } else if is_remote(path) {
+        fetch(&path.to_string_lossy())
+    } else {
+        fs::read_to_string(path).with_context(|| {
+            Failure::NotFound.error(format_args!("{} not found", display_path(path)))
+        })
+    }
+}
+
+/// Whether the Compose file is fetched over HTTP(S) instead of being read from the file system
+pub(crate) fn is_remote(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// The directory that the relative paths in a Compose file are resolved from, remote files leaving
+/// them relative to the project directory
+fn directory(path: &Path) -> &Path {
+    if is_remote(path) {
+        Path::new("")
+    } else {
+        path.parent().unwrap_or_else(|| Path::new(""))
+    }
+}
+
+fn fetch(url: &str) -> Result<String> {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(FETCH_TIMEOUT))
+        .build()
+        .new_agent();
+    let content = agent
+        .get(url)
+        .call()
+        .and_then(|mut response| {
+            response
+                .body_mut()
+                .with_config()
+                .limit(FETCH_LIMIT)
+                .read_to_string()
+        })
+        .map_err(|err| match err {
+            ureq::Error::StatusCode(404) => {
+                Failure::NotFound.error(format_args!("{url} not found")).into()
+            }
+            err => Error::from(err).context(format!("{url} could not be fetched")),
+        })?;
+
+    warning!("Relative paths in {url} are resolved from the project directory, not from the URL");
+
+    Ok(content)
+}
+
+fn interpolate_file(
+    path: &Path,
+    source: &str,
+    content: &Value,
+    variables: &IndexMap<String, String>,
+) -> Result<Value> {
+    let mut props = Vec::new();
+
+    interpolate(path, content, variables, &mut props).map_err(|err| {
+        let message = if props.is_empty() {
+            format!("{err:#}")
+        } else if let Some(location) = locate(source, &props) {
+            format!(
+                "{}:{}:{}: {}: {err:#}",
+                display_path(path),
+                location.line(),
+                location.column(),
+                props.join(".")
+            )
+        } else {
+            format!("{}: {err:#}", props.join("."))
+        };
+
+        Failure::Interpolation.error(message).into()
+    })
+}
+
+struct Fields<'a>(&'a mut &'static [&'static str]);
+
+impl<'de> Deserializer<'de> for Fields<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        *self.0 = fields;
+
+        Err(de::Error::custom("fields collected"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+fn fields<T>() -> &'static [&'static str]
+where
+    T: de::DeserializeOwned,
+{
+    let mut fields: &[&str] = &[];
+    let _ = T::deserialize(Fields(&mut fields));
+
+    fields
+}
+
+fn unused_property(path: &serde_ignored::Path, segments: &mut Vec<String>) {
+    match path {
+        serde_ignored::Path::Root => {}
+        serde_ignored::Path::Seq { parent, index } => {
+            unused_property(parent, segments);
+            segments.push(index.to_string());
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            unused_property(parent, segments);
+            segments.push(key.clone());
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => unused_property(parent, segments),
+    }
+}
+
+fn suggest_property(segments: &[&str]) -> Option<&'static str> {
+    let (property, fields) = match segments {
+        [property] => (property, fields::<Compose>()),
+        ["services", _, property] => (property, fields::<Service>()),
+        ["services", _, "blkio_config", property] => (property, fields::<BlkioConfig>()),
+        ["services", _, "deploy", property] => (property, fields::<DeployConfig>()),
+        ["services", _, "deploy", "resources", property] => (property, fields::<Resources>()),
+        ["services", _, "deploy", "resources", _, property] => (property, fields::<Resource>()),
+        ["services", _, "develop", property] => (property, fields::<Development>()),
+        ["services", _, "develop", "watch", _, property] => (property, fields::<WatchRule>()),
+        ["services", _, "extends", property] => (property, fields::<Extends>()),
+        ["services", _, "healthcheck", property] => (property, fields::<Healthcheck>()),
+        ["services", _, "logging", property] => (property, fields::<Logging>()),
+        ["networks", _, property] => (property, fields::<Network>()),
+        ["networks", _, "ipam", property] => (property, fields::<IpamConfig>()),
+        ["volumes", _, property] => (property, fields::<Volume>()),
+        ["secrets", _, property] => (property, fields::<Secret>()),
+        ["include", _, property] => (property, fields::<Include>()),
+        _ => return None,
+    };
+
+    fields
+        .iter()
+        .map(|field| (field, strsim::levenshtein(property, field)))
+        .filter(|(_, distance)| *distance <= 2)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(field, _)| *field)
+}
+
+// Duplicated mount targets are collapsed once deserialized, so they are looked for beforehand
+fn duplicate_mounts(content: &Value) -> Result<()> {
+    let Some(services) = content.get("services").and_then(Value::as_mapping) else {
+        return Ok(());
+    };
+
+    for (name, service) in services {
+        let Some(volumes) = service.get("volumes").and_then(Value::as_sequence) else {
+            continue;
+        };
+        let mut targets = IndexSet::new();
+
+        for volume in volumes {
+            let target = match volume {
+                Value::String(mount) => types::parse_service_volume(mount).ok().map(|volume| volume.target),
+                Value::Mapping(volume) => volume
+                    .get("target")
+                    .and_then(Value::as_str)
+                    .map(PathBuf::from),
+                _ => None,
+            };
+
+            if let Some(target) = target {
+                if !targets.insert(target.clone()) {
+                    bail!(
+                        "Service \"{}\" mounts two volumes at \"{}\"",
+                        name.as_str().unwrap_or_default(),
+                        target.display()
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Deserializes a Compose file, along with its unknown properties and the known ones they may be
+/// a typo of
+fn deserialize(
+    path: &Path,
+    content: &Value,
+    options: &Options,
+) -> Result<(Compose, IndexMap<String, Option<&'static str>>)> {
+    if !options.no_consistency {
+        duplicate_mounts(content).with_context(|| {
+            Failure::Specification
+                .error(format_args!("{} defines duplicate mounts", display_path(path)))
+        })?;
+    }
+
+    let content = serde_yaml::to_string(content)?;
+    let mut unused = IndexMap::new();
+
+    serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&content), |path| {
+        let mut segments = Vec::new();
+        unused_property(&path, &mut segments);
+
+        let segments = segments.iter().map(String::as_str).collect::<Vec<_>>();
+        unused.insert(segments.join("."), suggest_property(&segments));
+    })
+    .with_context(|| {
+        Failure::Specification.error(format_args!(
+            "{} does not follow the Compose specification",
+            display_path(path)
+        ))
+    })
+    .and_then(|mut file: Compose| {
+        // Environment files are relative to the Compose file referencing them, like included ones
+        let directory = directory(path);
+
+        for env_file in file
+            .services
+            .values_mut()
+            .flat_map(|service| &mut service.env_file)
+        {
+            env_file.path = directory.join(&env_file.path).absolutize()?.to_path_buf();
+        }
+
+        Ok((file, unused))
+    })
+}
+
+fn load_env_files(
+    service: &mut Service,
+    variables: &IndexMap<String, String>,
+    no_interpolate: bool,
+) -> Result<()> {
+    let mut variables = variables.clone();
+    let mut environment = IndexMap::new();
+
+    for env_file in mem::take(&mut service.env_file) {
+        let contents = match fs::read_to_string(&env_file.path) {
+            Ok(contents) => contents,
+            Err(_) if !env_file.required => continue,
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    Failure::NotFound.error(format_args!(
+                        "{} not found",
+                        display_path(&env_file.path)
+                    ))
+                })
+            }
+        };
+
+        for (key, mut value) in config::parse_env_file(&env_file.path, &contents)? {
+            if !no_interpolate {
+                value = interpolate_str(&value, &variables).with_context(|| {
+                    Failure::Interpolation
+                        .error(format_args!("{}: {key}", display_path(&env_file.path)))
+                })?;
+                variables.insert(key.clone(), value.clone());
+            }
+
+            environment.insert(key, Some(value));
+        }
+    }
+
+    // The variables set in the Compose file take precedence over the ones from the files
+    environment.extend(mem::take(&mut service.environment));
+    service.environment = environment;
+
+    Ok(())
+}
+
+fn load_file(
+    path: &Path,
+    options: &Options,
+    variables: &IndexMap<String, String>,
+) -> Result<Compose> {
+    let source = read(path)?;
+    let mut content = parse_source(path, &source)?;
+
+    if !options.no_interpolate {
+        content = interpolate_file(path, &source, &content, variables)?;
+    }
+
+    deserialize(path, &content, options).map(|(file, _)| file)
+}
+
+fn extend(
+    file: &Compose,
+    path: &Path,
+    name: &str,
+    options: &Options,
+    variables: &IndexMap<String, String>,
+    stack: &mut Vec<(PathBuf, String)>,
+) -> Result<Service> {
+    let service = file.services.get(name).ok_or_else(|| {
+        anyhow!(Failure::Specification.error(format_args!(
+            "Cannot extend service \"{name}\" as it is not defined in {}",
+            display_path(path)
+        )))
+    })?;
+    let Some(extends) = &service.extends else {
+        return Ok(service.clone());
+    };
+
+    let base_path = if let Some(base_file) = &extends.file {
+        directory(path)
+            .join(base_file)
+            .absolutize()?
+            .to_path_buf()
+    } else {
+        path.absolutize()?.to_path_buf()
+    };
+
+    stack.push((path.absolutize()?.to_path_buf(), name.to_string()));
+
+    if let Some(i) = stack
+        .iter()
+        .position(|(path, name)| *path == base_path && *name == extends.service)
+    {
+        bail!(Failure::Specification.error(format_args!(
+            "Circular reference found in extends: {} -> {}",
+            stack[i..].iter().map(|(_, name)| name).join(" -> "),
+            extends.service
+        )));
+    }
+
+    let mut base = if extends.file.is_some() {
+        let base_file = load_file(&base_path, options, variables)?;
+
+        extend(
+            &base_file,
+            &base_path,
+            &extends.service,
+            options,
+            variables,
+            stack,
+        )?
+    } else {
+        extend(
+            file,
+            path,
+            &extends.service,
+            options,
+            variables,
+            stack,
+        )?
+    };
+
+    stack.pop();
+
+    let mut service = service.clone();
+    service.extends = None;
+    base.merge(&service);
+
+    Ok(base)
+}
+
+fn resolve_extends(
+    file: &mut Compose,
+    path: &Path,
+    options: &Options,
+    variables: &IndexMap<String, String>,
+) -> Result<()> {
+    let services = file
+        .services
+        .keys()
+        .map(|name| {
+            extend(file, path, name, options, variables, &mut Vec::new())
+                .map(|service| (name.clone(), service))
+        })
+        .collect::<Result<IndexMap<_, _>>>()?;
+
+    file.services = services;
+
+    Ok(())
+}
+
+fn report_unused(
+    path: &Path,
+    unused: IndexMap<String, Option<&'static str>>,
+    policy: UnknownProperties,
+) -> Result<()> {
+    if !unused.is_empty() {
+        let message = format!(
+            "Unsupported/unknown properties in {}: {}",
+            display_path(path),
+            unused
+                .iter()
+                .map(|(property, suggestion)| match suggestion {
+                    Some(suggestion) => format!("{property} (did you mean \"{suggestion}\"?)"),
+                    None => property.clone(),
+                })
+                .join(", ")
+        );
+
+        match policy {
+            // The property is only given as a field when there is a single one
+            UnknownProperties::Warn => utils::warn_logged(Logged {
+                message,
+                file: Some(display_path(path).into_owned()),
+                path: unused
+                    .keys()
+                    .exactly_one()
+                    .ok()
+                    .cloned(),
+            }),
+            UnknownProperties::Ignore => {}
+            UnknownProperties::Deny => bail!(Failure::Specification.error(message)),
+        }
+    }
+
+    Ok(())
+}
+
+fn import<T>(
+    kind: &str,
+    resources: &mut IndexMap<String, T>,
+    imported: IndexMap<String, T>,
+    path: &Path,
+    origin: &Path,
+) -> Result<()> {
+    for (name, resource) in imported {
+        if resources.contains_key(&name) {
+            bail!(Failure::Specification.error(format_args!(
+                "{kind} \"{name}\" from {} conflicts with the one defined in {}",
+                display_path(origin),
+                display_path(path)
+            )));
+        }
+
+        resources.insert(name, resource);
+    }
+
+    Ok(())
+}
+
+fn include(
+    file: &mut Compose,
+    path: &Path,
+    options: &Options,
+    variables: &IndexMap<String, String>,
+    stack: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let directory = directory(path);
+    stack.push(path.absolutize()?.to_path_buf());
+
+    for include in mem::take(&mut file.include) {
+        let mut include_variables = variables.clone();
+
+        for env_file in &include.env_file {
+            let env_file = directory.join(env_file);
+            let contents = fs::read_to_string(&env_file).with_context(|| {
+                Failure::NotFound.error(format_args!("{} not found", env_file.display()))
+            })?;
+
+            for (key, value) in config::parse_env_file(&env_file, &contents)? {
+                // Variables from the environment and `--env` take precedence over the ones from
+                // the files
+                if !variables.contains_key(&key) {
+                    let value = interpolate_str(&value, &include_variables).with_context(|| {
+                        Failure::Interpolation.error(format_args!("{}: {key}", env_file.display()))
+                    })?;
+                    include_variables.insert(key, value);
+                }
+            }
+        }
+
+        let mut included = Compose::new();
+        let mut origin = PathBuf::new();
+
+        for included_path in &include.path {
+            let included_path = directory.join(included_path);
+            let absolute_path = included_path.absolutize()?.to_path_buf();
+
+            if let Some(i) = stack.iter().position(|path| *path == absolute_path) {
+                bail!(Failure::Specification.error(format_args!(
+                    "Circular reference found in include: {} -> {}",
+                    stack[i..].iter().map(|path| path.display()).join(" -> "),
+                    absolute_path.display()
+                )));
+            }
+
+            let source = read(&included_path)?;
+            let mut content = parse_source(&included_path, &source)?;
+
+            if !options.no_interpolate {
+                content = interpolate_file(&included_path, &source, &content, &include_variables)?;
+            }
+
+            // Relative paths are resolved from the directory of the included file by default
+            let project_directory = include
+                .project_directory
+                .as_ref()
+                .map_or_else(
+                    || self::directory(&included_path).to_path_buf(),
+                    |project_directory| directory.join(project_directory),
+                );
+            let mut file = types::with_base_directory(&project_directory, || {
+                let (mut file, unused) = deserialize(&included_path, &content, options)?;
+                report_unused(&included_path, unused, options.unknown_properties)?;
+                resolve_extends(&mut file, &included_path, options, &include_variables)?;
+
+                Ok(file)
+            })?;
+            self::include(&mut file, &included_path, options, &include_variables, stack)?;
+
+            included.merge(file);
+            origin = included_path;
+        }
+
+        import("Service", &mut file.services, included.services, path, &origin)?;
+        import("Network", &mut file.networks, included.networks, path, &origin)?;
+        import("Volume", &mut file.volumes, included.volumes, path, &origin)?;
+        import("Secret", &mut file.secrets, included.secrets, path, &origin)?;
+    }
+
+    stack.pop();
+
+    Ok(())
+}
+
+/// Snapshots the environment with the variables that take precedence over it, so that every
+/// reference to a variable resolves identically without reading the environment each time
+pub(crate) fn variables(config: &Config) -> IndexMap<String, String> {
+    let mut variables = env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect::<IndexMap<_, _>>();
+    variables.insert(
+        String::from("COMPOSE_FILE"),
+        config
+            .files
+            .iter()
+            .map(|file| file.display())
+            .join(PathSeparator::separator()),
+    );
+    variables.extend(config.variables.clone());
+
+    variables
+}
+
+fn load_value(path: &Path, no_interpolate: bool, variables: &IndexMap<String, String>) -> Result<Value> {
+    let source = read(path)?;
+    let mut content = parse_source(path, &source)?;
+    strip_tags(&mut content, &mut Vec::new(), &mut Vec::new());
+
+    if !no_interpolate {
+        content = interpolate_file(path, &source, &content, variables)?;
+    }
+
+    Ok(content)
+}
+
+// Unlike `extend`, which works on the normalized model, this keeps the raw values of the services
+fn extend_value(
+    file: &Value,
+    path: &Path,
+    name: &str,
+    no_interpolate: bool,
+    variables: &IndexMap<String, String>,
+    stack: &mut Vec<(PathBuf, String)>,
+) -> Result<Value> {
+    let service = file
+        .get("services")
+        .and_then(|services| services.get(name))
+        .ok_or_else(|| {
+            anyhow!(Failure::Specification.error(format_args!(
+                "Cannot extend service \"{name}\" as it is not defined in {}",
+                display_path(path)
+            )))
+        })?;
+    let Some(extends) = service.get("extends") else {
+        return Ok(service.clone());
+    };
+    let (base_service, base_file) = match extends {
+        Value::String(base_service) => (base_service.as_str(), None),
+        _ => (
+            extends
+                .get("service")
+                .and_then(Value::as_str)
+                .ok_or_else(|| {
+                    anyhow!(Failure::Specification
+                        .error(format_args!("Service \"{name}\" extends a service without a name")))
+                })?,
+            extends.get("file").and_then(Value::as_str),
+        ),
+    };
+
+    let base_path = if let Some(base_file) = base_file {
+        directory(path)
+            .join(base_file)
+            .absolutize()?
+            .to_path_buf()
+    } else {
+        path.absolutize()?.to_path_buf()
+    };
+
+    stack.push((path.absolutize()?.to_path_buf(), name.to_string()));
+
+    if let Some(i) = stack
+        .iter()
+        .position(|(path, name)| *path == base_path && name == base_service)
+    {
+        bail!(Failure::Specification.error(format_args!(
+            "Circular reference found in extends: {} -> {base_service}",
+            stack[i..].iter().map(|(_, name)| name).join(" -> ")
+        )));
+    }
+
+    let mut base = if base_file.is_some() {
+        let base_file = load_value(&base_path, no_interpolate, variables)?;
+
+        extend_value(
+            &base_file,
+            &base_path,
+            base_service,
+            no_interpolate,
+            variables,
+            stack,
+        )?
+    } else {
+        extend_value(file, path, base_service, no_interpolate, variables, stack)?
+    };
+
+    stack.pop();
+
+    let mut service = service.clone();
+
+    if let Some(service) = service.as_mapping_mut() {
+        service.remove("extends");
+    }
+
+    types::merge(&mut base, service);
+
+    Ok(base)
+}
+
+pub(crate) fn load_unnormalized(config: &Config, options: &Options) -> Result<Parsed<Value>> {
+    load_unnormalized_sources(config, &read_all(config)?, options)
+}
+
+/// Merges Compose files already read into memory as they are written, without normalizing them
+pub(crate) fn load_unnormalized_sources(
+    config: &Config,
+    sources: &[(PathBuf, String)],
+    options: &Options,
+) -> Result<Parsed<Value>> {
+    let parsed = collect(config, || merge_unnormalized(config, sources, options))?;
+    let sections = parsed
+        .file
+        .as_mapping()
+        .into_iter()
+        .flat_map(Mapping::keys)
+        .filter_map(Value::as_str)
+        .map(ToOwned::to_owned)
+        .collect();
+
+    Ok(Parsed { sections, ..parsed })
+}
+
+fn merge_unnormalized(
+    config: &Config,
+    sources: &[(PathBuf, String)],
+    options: &Options,
+) -> Result<Value> {
+    let variables = variables(config);
+    let mut combined_file = Value::Null;
+
+    for (path, source) in sources {
+        let mut content = parse_source(path, source)?;
+        let mut resets = Vec::new();
+        strip_tags(&mut content, &mut Vec::new(), &mut resets);
+
+        if !options.no_interpolate {
+            content = interpolate_file(path, source, &content, &variables)?;
+        }
+
+        if options.resolve_extends {
+            let services = content
+                .get("services")
+                .and_then(Value::as_mapping)
+                .map(|services| {
+                    services
+                        .keys()
+                        .filter_map(Value::as_str)
+                        .map(|name| {
+                            extend_value(
+                                &content,
+                                path,
+                                name,
+                                options.no_interpolate,
+                                &variables,
+                                &mut Vec::new(),
+                            )
+                            .map(|service| (Value::from(name), service))
+                        })
+                        .collect::<Result<Mapping>>()
+                })
+                .transpose()?;
+
+            if let Some(services) = services {
+                content["services"] = Value::Mapping(services);
+            }
+        }
+
+        for reset in resets {
+            remove(&mut combined_file, &reset);
+        }
+
+        types::merge(&mut combined_file, content);
+    }
+
+    Ok(combined_file)
+}
+
+/// Lists the variables referenced by the Compose files, with their literal default value if any
+pub(crate) fn referenced_names(config: &Config) -> Result<IndexMap<String, Option<String>>> {
+    let mut names = IndexMap::new();
+
+    for path in &config.files {
+        let source = read(path)?;
+        referenced(&parse_source(path, &source)?, &mut names);
+    }
+
+    Ok(names)
+}
+
+/// Resolves every variable referenced by the Compose files, as `file` was interpolated with
+pub(crate) fn referenced_variables(
+    config: &Config,
+    file: &Compose,
+) -> Result<IndexMap<String, Option<String>>> {
+    let mut variables = variables(config);
+
+    if let Some(name) = &file.name {
+        variables.insert(String::from("COMPOSE_PROJECT_NAME"), name.clone());
+    }
+
+    Ok(referenced_names(config)?
+        .into_keys()
+        .map(|name| {
+            let value = lookup(&variables, &name).ok();
+            (name, value)
+        })
+        .collect())
+}
+
+/// The top-level resources that none of the services use
+#[derive(Default, Debug)]
+pub(crate) struct Dangling {
+    pub(crate) networks: IndexSet<String>,
+    pub(crate) volumes: IndexSet<String>,
+    pub(crate) secrets: IndexSet<String>,
+}
+
+pub(crate) fn dangling(file: &Compose) -> Dangling {
+    let all_networks = file
+        .services
+        .values()
+        .flat_map(|service| service.networks.keys())
+        .cloned()
+        .collect::<IndexSet<_>>();
+    let all_volumes = file
+        .services
+        .values()
+        .flat_map(|service| {
+            service
+                .volumes
+                .iter()
+                .filter_map(|volume| match &volume.r#type {
+                    ServiceVolumeType::Volume(source) => source.clone(),
+                    _ => None,
+                })
+        })
+        .collect::<IndexSet<_>>();
+    let all_secrets = file
+        .services
+        .values()
+        .flat_map(|service| service.secrets.iter().map(|secret| secret.source.clone()))
+        .collect::<IndexSet<_>>();
+
+    Dangling {
+        networks: file
+            .networks
+            .keys()
+            .filter(|name| !all_networks.contains(*name))
+            .cloned()
+            .collect(),
+        volumes: file
+            .volumes
+            .keys()
+            .filter(|name| !all_volumes.contains(*name))
+            .cloned()
+            .collect(),
+        secrets: file
+            .secrets
+            .keys()
+            .filter(|name| !all_secrets.contains(*name))
+            .cloned()
+            .collect(),
+    }
+}
+
+pub(crate) fn prune(file: &mut Compose) {
+    let dangling = dangling(file);
+
+    file.networks
+        .retain(|network, _| !dangling.networks.contains(network));
+    file.volumes
+        .retain(|volume, _| !dangling.volumes.contains(volume));
+    file.secrets
+        .retain(|secret, _| !dangling.secrets.contains(secret));
+}
+
+fn read_all(config: &Config) -> Result<Vec<(PathBuf, String)>> {
+    config
+        .files
+        .iter()
+        .map(|path| read(path).map(|source| (path.clone(), source)))
+        .collect()
+}
+
+pub(crate) fn load(config: &Config, options: &Options) -> Result<Parsed<Compose>> {
+    let mut sections = IndexSet::new();
+    let parsed = collect(config, || {
+        load_sources(config, &read_all(config)?, options, &mut sections)
+    })?;
+
+    Ok(Parsed { sections, ..parsed })
+}
+
+/// Derives the project name from a directory, replacing the characters that are not allowed in
+/// project names
+fn directory_project_name(directory: &Path) -> Result<String> {
+    let name = directory
+        .file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    let name = regex!(r"[^a-z0-9_-]").replace_all(&name, "_");
+    let name = name.trim_start_matches(['_', '-']);
+
+    if name.is_empty() {
+        bail!(
+            "A project name cannot be derived from {}, use --project-name instead",
+            directory.display()
+        );
+    }
+
+    Ok(name.to_owned())
+}
+
+/// Loads Compose files already read into memory, each with the path it is displayed and resolved
+/// relative to, recording the top-level sections they declare
+fn load_sources(
+    config: &Config,
+    sources: &[(PathBuf, String)],
+    options: &Options,
+    sections: &mut IndexSet<String>,
+) -> Result<Compose> {
+    let mut variables = variables(config);
+    let re = regex!(r"^[a-z0-9][a-z0-9_-]*$");
+    let mut files = Vec::new();
+    let mut declared_name: Option<(String, &PathBuf)> = None;
+
+    for (i, (path, source)) in sources.iter().enumerate() {
+        let mut content = parse_source(path, source)?;
+        let mut resets = Vec::new();
+        strip_tags(&mut content, &mut Vec::new(), &mut resets);
+
+        if let Some(values) = content.as_mapping_mut() {
+            sections.extend(values.keys().filter_map(Value::as_str).map(ToOwned::to_owned));
+
+            let declared = config.project_name.is_none() && values.contains_key("name");
+            let name = if config.project_name.is_some() {
+                config.project_name.clone()
+            } else if let Some((_, name)) = values.iter().find(|(key, _)| *key == "name") {
+                // Numbers would not be written back as they were, e.g. `1.0` becoming "1"
+                match name {
+                    Value::String(name) => Some(name.clone()),
+                    Value::Null => Some(String::new()),
+                    name => bail!(Failure::Specification.error(format_args!(
+                        "Project name {} in {} must be a string, it can be quoted to be one",
+                        serde_yaml::to_string(name).unwrap_or_default().trim_end(),
+                        display_path(path)
+                    ))),
+                }
+            } else if i == 0 {
+                Some(String::new())
+            } else {
+                None
+            };
+
+            if let Some(mut name) = name {
+                if declared && !name.is_empty() {
+                    match &declared_name {
+                        Some((origin_name, origin)) if *origin_name != name => {
+                            bail!(Failure::Specification.error(format_args!(
+                                "Project name \"{name}\" from {} conflicts with \"{origin_name}\" defined in {}",
+                                display_path(path),
+                                display_path(origin)
+                            )))
+                        }
+                        Some(_) => {}
+                        None => declared_name = Some((name.clone(), path)),
+                    }
+                }
+
+                // Without a name in the files, the one from the environment is preferred to the
+                // name of the current directory
+                if name.is_empty() {
+                    name = lookup(&variables, "COMPOSE_PROJECT_NAME")
+                        .ok()
+                        .filter(|name| !name.is_empty())
+                        .map_or_else(|| directory_project_name(&env::current_dir()?), Ok)?;
+                }
+
+                if !re.is_match(&name) {
+                    bail!(Failure::Specification.error(format_args!(
+                        "Project name \"{name}\" must only contain lowercase letters, digits, dashes and underscores, and start with a letter or a digit"
+                    )));
+                }
+
+                variables.insert(String::from("COMPOSE_PROJECT_NAME"), name.clone());
+                values.insert(Value::String(String::from("name")), Value::String(name));
+            }
+        }
+
+        if !options.no_interpolate {
+            content = interpolate_file(path, source, &content, &variables)?;
+        }
+
+        let (mut file, unused) = deserialize(path, &content, options)?;
+        resolve_extends(&mut file, path, options, &variables)?;
+        include(&mut file, path, options, &variables, &mut Vec::new())?;
+
+        files.push((path, file, unused, resets));
+    }
+
+    let mut combined_file = Compose::new();
+    let mut origins = IndexMap::new();
+
+    for (path, file, unused, resets) in files {
+        report_unused(path, unused, options.unknown_properties)?;
+
+        if file.version.is_some() {
+            utils::warn_logged(Logged {
+                message: format!(
+                    "`version` is obsolete and ignored, it can be removed from {}",
+                    display_path(path)
+                ),
+                file: Some(display_path(path).into_owned()),
+                path: Some(String::from("version")),
+            });
+        }
+
+        // Definitions are merged with the ones of the previous files, unless `!override` replaces
+        // them whole
+        for (kind, section, name) in file
+            .services
+            .keys()
+            .map(|name| ("Service", "services", name))
+            .chain(file.networks.keys().map(|name| ("Network", "networks", name)))
+            .chain(file.volumes.keys().map(|name| ("Volume", "volumes", name)))
+            .chain(file.secrets.keys().map(|name| ("Secret", "secrets", name)))
+        {
+            if let Some(origin) = origins.insert((kind, name.clone()), path) {
+                if origin != path && resets.iter().any(|reset| reset == &[section, name]) {
+                    warning!(
+                        "{kind} \"{name}\" from {} replaces the one defined in {}",
+                        display_path(path),
+                        display_path(origin)
+                    );
+                }
+            }
+        }
+
+        for reset in resets {
+            combined_file.reset(&reset);
+        }
+
+        combined_file.merge(file);
+    }
+
+    let disabled_services = combined_file
+        .services
+        .iter()
+        .filter(|(_, service)| {
+            !service.profiles.is_empty()
+                && !service
+                    .profiles
+                    .iter()
+                    .any(|profile| config.profiles.contains(profile))
+        })
+        .map(|(name, _)| name.clone())
+        .collect::<IndexSet<_>>();
+
+    combined_file
+        .services
+        .retain(|name, _| !disabled_services.contains(name));
+
+    for (name, service) in &mut combined_file.services {
+        service
+            .depends_on
+            .retain(|dependency, _| !disabled_services.contains(dependency));
+        load_env_files(service, &variables, options.no_interpolate)
+            .with_context(|| anyhow!("Service \"{name}\" could not load its environment files"))?;
+    }
+
+    combined_file
+        .networks
+        .entry(String::from("default"))
+        .or_default();
+
+    if !options.no_prune {
+        prune(&mut combined_file);
+    }
+
+    for (name, network) in &mut combined_file.networks {
+        network.name.get_or_insert_with(|| {
+            match (network.external.unwrap_or_default(), &combined_file.name) {
+                (false, Some(project_name)) => format!("{project_name}_{name}"),
+                _ => name.clone(),
+            }
+        });
+    }
+
+    for (name, volume) in &mut combined_file.volumes {
+        volume.name.get_or_insert_with(|| {
+            match (volume.external.unwrap_or_default(), &combined_file.name) {
+                (false, Some(project_name)) => format!("{project_name}_{name}"),
+                _ => name.clone(),
+            }
+        });
+    }
+
+    for (name, secret) in &mut combined_file.secrets {
+        secret.name.get_or_insert_with(|| {
+            match (secret.external.unwrap_or_default(), &combined_file.name) {
+                (false, Some(project_name)) => format!("{project_name}_{name}"),
+                _ => name.clone(),
+            }
+        });
+    }
+
+    for (name, service) in &combined_file.services {
+        if service.scale.is_some() {
+            warning!(
+                "`scale` is deprecated, use the `deploy.replicas` element instead"
+            );
+        }
+
+        if service.mem_limit.is_some() {
+            warning!(
+                "`mem_limit` is deprecated, use the `deploy.limits.memory` element instead"
+            );
+        }
+
+        if service.cpus.is_some() {
+            warning!(
+                "`cpus` is deprecated, use the `deploy.reservations.cpus` element instead"
+            );
+        }
+
+        if service.mem_reservation.is_some() {
+            warning!(
+                "`mem_reservation` is deprecated, use the `deploy.reservations.memory` element instead"
+            );
+        }
+
+        if !service.links.is_empty() {
+            warning!(
+                "`links` is deprecated, use the `depends_on` element and the name of the services on their networks instead"
+            );
+        }
+
+        if service.pids_limit.is_some() {
+            warning!(
+                "`pids_limit` is deprecated, use the `deploy.reservations.pids` element instead"
+            );
+        }
+
+        if let Some(architecture) = service
+            .platform
+            .as_deref()
+            .and_then(|platform| platform_architecture(platform).ok())
+            .filter(|architecture| !ARCHITECTURES.contains(architecture))
+        {
+            warning!("Service \"{name}\" uses the unusual architecture \"{architecture}\"");
+        }
+
+        if service.healthcheck.as_ref().is_some_and(|healthcheck| {
+            healthcheck.disable.unwrap_or_default()
+                && !healthcheck.test.is_empty()
+                && healthcheck.test != ["NONE"]
+        }) {
+            warning!(
+                "Service \"{name}\" has a healthcheck test that is ignored as its healthcheck is disabled"
+            );
+        }
+
+        if service.depends_on.values().any(|dependency| {
+            matches!(
+                dependency.condition,
+                Condition::Healthy | Condition::CompletedSuccessfully
+            )
+        }) {
+            warning!(
+                "\"service_healthy\" and \"service_completed_successfully\" are unsupported and will degrade to \"service_started\""
+            );
+        }
+    }
+
+    Ok(combined_file)
+}
+
+fn find_cycle<'a>(
+    dependencies: &DiGraphMap<&'a String, ()>,
+    component: &IndexSet<&'a String>,
+    path: &mut Vec<&'a String>,
+) -> bool {
+    let current = *path.last().unwrap();
+
+    for dependency in dependencies.neighbors(current) {
+        if dependency == path[0] {
+            return true;
+        }
+
+        if component.contains(dependency) && !path.contains(&dependency) {
+            path.push(dependency);
+
+            if find_cycle(dependencies, component, path) {
+                return true;
+            }
+
+            path.pop();
+        }
+    }
+
+    false
+}
+
+fn port_range(port: &str) -> Result<(u16, u16)> {
+    let (start, end) = port.split_once('-').unwrap_or((port, port));
+    let parse = |port: &str| match port.parse::<u32>() {
+        Ok(port) => u16::try_from(port).map_err(|_| anyhow!("Port {port} exceeds 65535")),
+        Err(_) => Err(anyhow!("\"{port}\" is not a valid port")),
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+
+    if start > end {
+        bail!("\"{port}\" is not a valid port range");
+    }
+
+    Ok((start, end))
+}
+
+/// Validates a container port or range exposed without publishing it, e.g. "3000-3005/udp"
+fn validate_expose(expose: &str) -> Result<()> {
+    let (port, protocol) = expose.split_once('/').unwrap_or((expose, "tcp"));
+
+    if !["tcp", "udp", "sctp"].contains(&protocol) {
+        bail!("\"{protocol}\" is not a valid protocol, expected one of: tcp, udp, sctp");
+    }
+
+    port_range(port).map(|_| ())
+}
+
+fn validate_port(port: &Port) -> Result<()> {
+    let (start, end) = port_range(&port.target)?;
+
+    if let Some(published) = &port.published {
+        let (published_start, published_end) = port_range(published)?;
+
+        if start != end
+            && published_start != published_end
+            && end - start != published_end - published_start
+        {
+            bail!("Published and target port ranges must have the same size");
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the architecture of a platform in the `os/arch[/variant]` format
+fn platform_architecture(platform: &str) -> Result<&str> {
+    let parts = platform.split('/').collect::<Vec<_>>();
+
+    if !(2..=3).contains(&parts.len())
+        || parts.iter().any(|part| {
+            part.is_empty() || !part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+    {
+        bail!("expected \"os/arch[/variant]\"");
+    }
+
+    Ok(parts[1])
+}
+
+// -1 stands for an unlimited resource
+fn validate_ulimit(limit: &ResourceLimit) -> Result<()> {
+    match *limit {
+        ResourceLimit::Single(limit) if limit < -1 => bail!("{limit} is negative"),
+        ResourceLimit::Double { soft, hard } => {
+            for limit in [soft, hard] {
+                if limit < -1 {
+                    bail!("{limit} is negative");
+                }
+            }
+
+            if hard != -1 && (soft == -1 || soft > hard) {
+                bail!("the soft limit {soft} exceeds the hard limit {hard}");
+            }
+
+            Ok(())
+        }
+        ResourceLimit::Single(_) => Ok(()),
+    }
+}
+
+pub(crate) fn validate(file: &Compose) -> Vec<Error> {
+    let mut errors = Vec::new();
+
+    for (name, service) in &file.services {
+        if service.image.is_none() {
+            errors.push(anyhow!(
+                "Service \"{name}\" does not have an image specified"
+            ));
+        }
+
+        if service.network_mode.is_some() && service.networks.keys().any(|key| key != "default") {
+            errors.push(anyhow!(
+                "Service \"{name}\" cannot have networks due to the network mode set"
+            ));
+        }
+
+        if let Some(target) = service
+            .network_mode
+            .as_deref()
+            .and_then(|network_mode| network_mode.strip_prefix("service:"))
+        {
+            if !file.services.contains_key(target) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" uses the network stack of undefined service \"{target}\""
+                ));
+            }
+        }
+
+        if service.network_mode.as_deref().unwrap_or_default() == "host"
+            && !service.ports.is_empty()
+        {
+            errors.push(anyhow!(
+                "Service \"{name}\" cannot have port mappings due to host network mode"
+            ));
+        }
+
+        if service.network_mode.as_deref().unwrap_or_default() == "host"
+            && !service.extra_hosts.is_empty()
+        {
+            errors.push(anyhow!(
+                "Service \"{name}\" cannot have extra hosts due to host network mode"
+            ));
+        }
+
+        if service.network_mode.as_deref().unwrap_or_default() == "host"
+            && !service.links.is_empty()
+        {
+            errors.push(anyhow!(
+                "Service \"{name}\" cannot have links due to host network mode"
+            ));
+        }
+
+        if service.container_name.is_some()
+            && service
+                .deploy
+                .as_ref()
+                .and_then(|deploy| deploy.replicas)
+                .or(service.scale)
+                .unwrap_or(1)
+                > 1
+        {
+            errors.push(anyhow!(
+                "Service \"{name}\" cannot scale beyond one container as it has a container name"
+            ));
+        }
+
+        if service
+            .labels
+            .keys()
+            .any(|label| label.starts_with("io.podman.compose"))
+        {
+            errors.push(anyhow!(
+                "Service \"{name}\" cannot have labels starting with \"io.podman.compose\""
+            ));
+        }
+
+        if let Some(healthcheck) = &service.healthcheck {
+            if healthcheck.retries == Some(0) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" must have a positive number of healthcheck retries"
+                ));
+            }
+        }
+
+        if let Some(platform) = &service.platform {
+            if let Err(err) = platform_architecture(platform) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" has an invalid platform \"{platform}\": {err}"
+                ));
+            }
+        }
+
+        for rule in service.develop.iter().flat_map(|develop| &develop.watch) {
+            if rule.target.is_none() && rule.action != WatchAction::Rebuild {
+                errors.push(anyhow!(
+                    "Service \"{name}\" needs a target to sync \"{}\" to",
+                    rule.path.display()
+                ));
+            }
+        }
+
+        for (limit, value) in &service.ulimits {
+            if let Err(err) = validate_ulimit(value) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" has an invalid ulimit \"{limit}\": {err}"
+                ));
+            }
+        }
+
+        if let (Some(mem_limit), Some(SwapLimit::Limited(memswap_limit))) =
+            (service.mem_limit, &service.memswap_limit)
+        {
+            if *memswap_limit < mem_limit {
+                errors.push(anyhow!(
+                    "Service \"{name}\" has a `memswap_limit` ({memswap_limit:#}) lower than its \
+                     `mem_limit` ({mem_limit:#})"
+                ));
+            }
+        }
+
+        for expose in &service.expose {
+            if let Err(err) = validate_expose(expose) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" has an invalid exposed port \"{expose}\": {err}"
+                ));
+            }
+        }
+
+        for port in &service.ports {
+            if let Err(err) = validate_port(port) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" has an invalid port mapping \"{port}\": {err}"
+                ));
+            }
+        }
+
+        for link in &service.links {
+            match link.split(':').collect::<Vec<_>>()[..] {
+                [target] | [target, _] if !link.split(':').any(str::is_empty) => {
+                    if !file.services.contains_key(target) {
+                        errors.push(anyhow!(
+                            "Service \"{name}\" links to undefined service \"{target}\""
+                        ));
+                    }
+                }
+                _ => errors.push(anyhow!(
+                    "Service \"{name}\" has an invalid link \"{link}\", expected \"service\" or \"service:alias\""
+                )),
+            }
+        }
+
+        for (dependency, Dependency { condition, .. }) in &service.depends_on {
+            if !file.services.contains_key(dependency) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" depends on undefined service \"{dependency}\""
+                ));
+            }
+
+            if let Condition::Unknown(condition) = condition {
+                errors.push(anyhow!(
+                    "Service \"{name}\" depends on \"{dependency}\" with unknown condition \"{condition}\", expected one of: service_started, service_healthy, service_completed_successfully"
+                ));
+            }
+        }
+
+        for network in service.networks.keys() {
+            if !file.networks.contains_key(network) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" refers to undefined network \"{network}\""
+                ));
+            }
+        }
+
+        for volume in service
+            .volumes
+            .iter()
+            .filter_map(|volume| match &volume.r#type {
+                ServiceVolumeType::Volume(source) => source.as_ref(),
+                _ => None,
+            })
+        {
+            if !file.volumes.contains_key(volume) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" refers to undefined volume \"{volume}\""
+                ));
+            }
+        }
+
+        for secret in &service.secrets {
+            if !file.secrets.contains_key(&secret.source) {
+                errors.push(anyhow!(
+                    "Service \"{name}\" refers to undefined secret \"{}\"",
+                    secret.source
+                ));
+            }
+        }
+    }
+
+    let mut published_ports = IndexMap::new();
+    let mut conflicts = IndexSet::new();
+
+    for (name, service) in file
+        .services
+        .iter()
+        .filter(|(_, service)| service.network_mode.as_deref() != Some("host"))
+    {
+        for (i, port) in service.ports.iter().enumerate() {
+            if validate_port(port).is_err() {
+                continue;
+            }
+
+            let Some(Ok((start, end))) = port.published.as_deref().map(port_range) else {
+                continue;
+            };
+
+            // A range published for a single target port is a pool to pick a free host port from
+            if start != end && port_range(&port.target).is_ok_and(|(start, end)| start == end) {
+                continue;
+            }
+
+            for published in start..=end {
+                let host_ip = port.host_ip.as_deref().unwrap_or_default();
+
+                if let Some(other) =
+                    published_ports.insert((host_ip, published, &port.protocol), name)
+                {
+                    if conflicts.insert((other, name, i)) {
+                        let host_ip = if host_ip.is_empty() {
+                            String::new()
+                        } else {
+                            format!("{host_ip}:")
+                        };
+
+                        errors.push(if other == name {
+                            anyhow!(
+                                "Service \"{name}\" publishes host port {host_ip}{published}/{} more than once",
+                                port.protocol
+                            )
+                        } else {
+                            anyhow!(
+                                "Services \"{other}\" and \"{name}\" both publish host port {host_ip}{published}/{}",
+                                port.protocol
+                            )
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    for (name, network) in &file.networks {
+        if network.external.unwrap_or_default()
+            && (network.driver.is_some()
+                || !network.driver_opts.is_empty()
+                || network.enable_ipv6.is_some()
+                || network.ipam.is_some()
+                || network.internal.is_some()
+                || !network.labels.is_empty())
+        {
+            errors.push(anyhow!(
+                "Conflicting parameters specified for network \"{name}\""
+            ));
+        }
+
+        // An external resource can be given the name of an existing one, as long as it is not one
+        // that is created by the project
+        if network.external.unwrap_or_default() {
+            let external_name = network.name.as_deref().unwrap_or(name);
+
+            if let Some(other) = file.networks.iter().find_map(|(other, network)| {
+                (!network.external.unwrap_or_default()
+                    && network.name.as_deref().unwrap_or(other) == external_name)
+                    .then_some(other)
+            }) {
+                errors.push(anyhow!(
+                    "External network \"{name}\" refers to a network created by network \"{other}\""
+                ));
+            }
+        }
+
+        if network
+            .labels
+            .keys()
+            .any(|label| label.starts_with("io.podman.compose"))
+        {
+            errors.push(anyhow!(
+                "Network \"{name}\" cannot have labels starting with \"io.podman.compose\""
+            ));
+        }
+    }
+
+    for (name, volume) in &file.volumes {
+        if volume.external.unwrap_or_default()
+            && (volume.driver.is_some()
+                || !volume.driver_opts.is_empty()
+                || !volume.labels.is_empty())
+        {
+            errors.push(anyhow!(
+                "Conflicting parameters specified for volume \"{name}\""
+            ));
+        }
+
+        if volume.external.unwrap_or_default() {
+            let external_name = volume.name.as_deref().unwrap_or(name);
+
+            if let Some(other) = file.volumes.iter().find_map(|(other, volume)| {
+                (!volume.external.unwrap_or_default()
+                    && volume.name.as_deref().unwrap_or(other) == external_name)
+                    .then_some(other)
+            }) {
+                errors.push(anyhow!(
+                    "External volume \"{name}\" refers to a volume created by volume \"{other}\""
+                ));
+            }
+        }
+
+        if volume
+            .labels
+            .keys()
+            .any(|label| label.starts_with("io.podman.compose"))
+        {
+            errors.push(anyhow!(
+                "Volume \"{name}\" cannot have labels starting with \"io.podman.compose\""
+            ));
+        }
+    }
+
+    for (name, secret) in &file.secrets {
+        let sources = [
+            secret.file.is_some(),
+            secret.environment.is_some(),
+            secret.content.is_some(),
+        ]
+        .into_iter()
+        .filter(|source| *source)
+        .count();
+
+        if sources > 1 || (secret.external.unwrap_or_default() && sources > 0) {
+            errors.push(anyhow!(
+                "Conflicting parameters specified for secret \"{name}\""
+            ));
+        }
+    }
+
+    let dependencies = file
+        .services
+        .iter()
+        .flat_map(|(from, service)| service.depends_on.keys().map(move |to| (from, to, ())))
+        .collect::<DiGraphMap<_, _>>();
+
+    for component in tarjan_scc(&dependencies) {
+        if component.len() == 1 && !dependencies.contains_edge(component[0], component[0]) {
+            continue;
+        }
+
+        let component = component
+            .into_iter()
+            .sorted_by_key(|service| file.services.get_index_of(*service))
+            .collect::<IndexSet<_>>();
+        let mut path = vec![component[0]];
+
+        if find_cycle(&dependencies, &component, &mut path) {
+            errors.push(anyhow!(
+                "Dependency cycle detected: {} -> {}",
+                path.iter().join(" -> "),
+                path[0]
+            ));
+        }
+    }
+
+    errors
+}
+
+pub(crate) fn parse(config: &Config, options: &Options) -> Result<Parsed<Compose>> {
+    parse_sources(config, &read_all(config)?, options)
+}
+
+/// Parses Compose files already read into memory, without any file I/O besides the ones they
+/// reference, e.g. through `extends` or `include`
+pub(crate) fn parse_sources(
+    config: &Config,
+    sources: &[(PathBuf, String)],
+    options: &Options,
+) -> Result<Parsed<Compose>> {
+    let mut sections = IndexSet::new();
+    let parsed = collect(config, || {
+        let file = load_sources(config, sources, options, &mut sections)?;
+
+        if !options.no_consistency {
+            if let Some(err) = validate(&file).into_iter().next() {
+                bail!(Failure::Specification.error(format_args!("{err:#}")));
+            }
+        }
+
+        Ok(file)
+    })?;
+
+    Ok(Parsed { sections, ..parsed })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, Write},
+        iter,
+        net::TcpListener,
+        path::PathBuf,
+        thread,
+    };
+
+    use assert_matches::assert_matches;
+    use indexmap::indexmap;
+    use pretty_assertions::assert_eq;
+    use serde_yaml::Value;
+    use test_generator::test_resources;
+
+    use super::*;
+
+    // The environment is snapshotted under the given variables, as `variables()` does
+    fn interpolate(value: &Value, variables: &IndexMap<String, String>) -> Result<Value> {
+        let mut snapshot = env::vars().collect::<IndexMap<_, _>>();
+        snapshot.extend(variables.clone());
+
+        super::interpolate(Path::new("compose.yaml"), value, &snapshot, &mut Vec::new())
+    }
+
+    #[test_resources("tests/fixtures/**/*.y*ml")]
+    fn parse(resource: &str) {
+        let config = Config {
+            files: vec![PathBuf::from(resource)],
+            ..Config::default()
+        };
+
+        assert_matches!(super::parse(&config, &Options::default()), Ok(_));
+    }
+
+    #[test]
+    fn extends() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/extends/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::parse(&config, &Options::default()).unwrap().file;
+
+        for name in ["web", "worker"] {
+            let service = &file.services[name];
+
+            assert_eq!(service.image, Some(String::from("busybox")));
+            assert!(service.extends.is_none());
+            assert_eq!(
+                service.environment.get("ROLE"),
+                Some(&Some(String::from("web")))
+            );
+            assert_eq!(
+                service.environment.get("DEBUG"),
+                Some(&Some(String::from("false")))
+            );
+        }
+
+        assert!(file.services["web"].command.is_empty());
+        assert_eq!(file.services["worker"].command, vec!["worker"]);
+    }
+
+    #[test]
+    fn extends_cycle() {
+        let mut file = serde_yaml::from_str::<Compose>(
+            "
+            services:
+              web:
+                image: busybox
+                extends: base
+              base:
+                extends: web
+            ",
+        )
+        .unwrap();
+        let err = super::resolve_extends(
+            &mut file,
+            Path::new("compose.yaml"),
+            &Options {
+                no_interpolate: true,
+                ..Options::default()
+            },
+            &IndexMap::new(),
+        ).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Circular reference found in extends: web -> base -> web"
+        );
+    }
+
+    #[test]
+    fn merge_keys() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/merge-keys/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::parse(&config, &Options::default()).unwrap().file;
+        let restart = |name: &str| {
+            file.services[name]
+                .restart
+                .as_ref()
+                .map(ToString::to_string)
+        };
+
+        assert!(file
+            .services
+            .values()
+            .all(|service| service.image == Some(String::from("busybox"))));
+        assert_eq!(restart("web").as_deref(), Some("unless-stopped"));
+        assert_eq!(restart("worker").as_deref(), Some("no"));
+        assert_eq!(file.services["worker"].labels["tier"], "backend");
+        assert_eq!(restart("job").as_deref(), Some("always"));
+        assert_eq!(file.services["job"].init, Some(true));
+    }
+
+    #[test]
+    fn unknown_properties() {
+        let (_, unused) = super::deserialize(
+            Path::new("compose.yaml"),
+            &serde_yaml::from_str(
+                "services:\n  web:\n    image: busybox\n    enviroment: {}\n    healthcheck:\n      intervl: 5s\n    foo: bar\nnetworkz: {}\n",
+            )
+            .unwrap(),
+            &Options::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            unused.into_iter().collect::<Vec<_>>(),
+            vec![
+                (String::from("services.web.enviroment"), Some("environment")),
+                (String::from("services.web.healthcheck.intervl"), Some("interval")),
+                (String::from("services.web.foo"), None),
+                (String::from("networkz"), Some("networks")),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_properties_policy() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/unknown-properties/compose.yaml")],
+            ..Config::default()
+        };
+
+        for unknown_properties in [UnknownProperties::Warn, UnknownProperties::Ignore] {
+            let options = Options {
+                unknown_properties,
+                ..Options::default()
+            };
+            assert!(super::load(&config, &options).is_ok());
+        }
+
+        let options = Options {
+            unknown_properties: UnknownProperties::Deny,
+            ..Options::default()
+        };
+        assert!(super::load(&config, &options)
+            .unwrap_err()
+            .to_string()
+            .contains("services.web.enviroment"));
+    }
+
+    #[test]
+    fn json() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/json/compose.json")],
+            ..Config::default()
+        };
+        let file = temp_env::with_var_unset("TAG", || {
+            super::parse(&config, &Options::default()).unwrap().file
+        });
+
+        assert_eq!(file.services["web"].image, Some(String::from("busybox:latest")));
+
+        let err = super::parse_source(Path::new("compose.json"), "services:\n  web: {}\n")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "compose.json is not valid JSON");
+    }
+
+    #[test]
+    fn referenced_variables() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/project-name/compose.yaml")],
+            ..Config::default()
+        };
+        let variables = temp_env::with_var_unset("COMPOSE_PROJECT_NAME", || {
+            let file = super::parse(&config, &Options::default()).unwrap().file;
+            super::referenced_variables(&config, &file).unwrap()
+        });
+
+        assert_eq!(
+            variables,
+            indexmap! {
+                String::from("COMPOSE_PROJECT_NAME") => Some(String::from("myproject")),
+            }
+        );
+
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/json/compose.json")],
+            ..Config::default()
+        };
+        let variables = temp_env::with_var_unset("TAG", || {
+            let file = super::parse(&config, &Options::default()).unwrap().file;
+            super::referenced_variables(&config, &file).unwrap()
+        });
+
+        assert_eq!(variables, indexmap! { String::from("TAG") => None });
+    }
+
+    #[test]
+    fn anchors() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/anchors/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::parse(&config, &Options::default()).unwrap().file;
+        let (web, worker) = (&file.services["web"], &file.services["worker"]);
+
+        assert_eq!(worker.image.as_deref(), Some("busybox"));
+        assert_eq!(worker.command, web.command);
+        assert_eq!(worker.environment, web.environment);
+        assert_eq!(worker.volumes[0], web.volumes[0]);
+        assert_eq!(
+            serde_yaml::to_value(&worker.logging).unwrap(),
+            serde_yaml::to_value(&web.logging).unwrap()
+        );
+        assert_eq!(worker.labels["max-size"], "10m");
+
+        let file = super::load_unnormalized(&config, &Options::default()).unwrap().file;
+        let yaml = serde_yaml::to_string(&file).unwrap();
+        let json = serde_json::to_string(&file).unwrap();
+
+        assert!(!yaml.contains('&') && !yaml.contains('*'));
+        assert_eq!(
+            file["services"]["worker"]["logging"]["options"]["max-size"],
+            Value::String(String::from("10m"))
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(&json).unwrap()["services"]["worker"]["volumes"][0],
+            Value::String(String::from("/data:/data"))
+        );
+    }
+
+    #[test]
+    fn env_files() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/env-file/compose.yaml")],
+            ..Config::default()
+        };
+        let file = temp_env::with_var("PORT", None::<&str>, || {
+            super::parse(&config, &Options::default())
+        })
+        .unwrap()
+        .file;
+        let service = &file.services["web"];
+
+        assert!(service.env_file.is_empty());
+        assert_eq!(
+            service.environment,
+            indexmap! {
+                String::from("HOST") => Some(String::from("db")),
+                String::from("URL") => Some(String::from("postgres://db:5432")),
+                String::from("ROLE") => Some(String::from("web")),
+                String::from("DEBUG") => Some(String::from("1")),
+            }
+        );
+
+        let sources = [(
+            PathBuf::from("tests/fixtures/env-file/compose.yaml"),
+            String::from("services:\n  web:\n    image: busybox\n    env_file: [missing.env]\n"),
+        )];
+
+        assert_eq!(
+            super::parse_sources(&config, &sources, &Options::default())
+                .unwrap_err()
+                .to_string(),
+            "Service \"web\" could not load its environment files"
+        );
+    }
+
+    #[test]
+    fn no_consistency() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/no-consistency/compose.json")],
+            ..Config::default()
+        };
+
+        assert_matches!(super::parse(&config, &Options::default()), Err(_));
+        assert_matches!(
+            super::parse(
+                &config,
+                &Options {
+                    no_consistency: true,
+                    ..Options::default()
+                }
+            ),
+            Ok(_)
+        );
+    }
+
+    #[test]
+    fn duplicate_mounts() {
+        let content = serde_yaml::from_str(
+            "services:\n  web:\n    volumes:\n      - data:/data\n      - type: bind\n        source: ./data\n        target: /data\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            super::duplicate_mounts(&content).unwrap_err().to_string(),
+            "Service \"web\" mounts two volumes at \"/data\""
+        );
+
+        let content = serde_yaml::from_str(
+            "services:\n  web:\n    volumes:\n      - data:/data\n      - /data:/data2:ro\n",
+        )
+        .unwrap();
+
+        assert_matches!(super::duplicate_mounts(&content), Ok(()));
+
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from(
+                "services:\n  web:\n    image: busybox\n    volumes:\n      - data:/data\n      - ./data:/data\nvolumes:\n  data:\n",
+            ),
+        )];
+
+        assert!(super::parse_sources(&config, &sources, &Options::default())
+            .unwrap_err()
+            .to_string()
+            .starts_with("compose.yaml defines duplicate mounts"));
+        assert_matches!(
+            super::parse_sources(
+                &config,
+                &sources,
+                &Options {
+                    no_consistency: true,
+                    ..Options::default()
+                }
+            ),
+            Ok(_)
+        );
+    }
+
+    #[test]
+    fn include() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/include/compose.yaml")],
+            ..Config::default()
+        };
+        let file = temp_env::with_var_unset("POSTGRES_TAG", || {
+            super::parse(&config, &Options::default()).unwrap().file
+        });
+
+        assert_eq!(
+            file.services.keys().collect::<Vec<_>>(),
+            vec!["web", "db"]
+        );
+        assert_eq!(file.services["db"].image, Some(String::from("postgres:16")));
+        assert!(file.volumes.contains_key("data"));
+        assert!(file.include.is_empty());
+
+        let config = Config {
+            variables: IndexMap::from([(String::from("POSTGRES_TAG"), String::from("17"))]),
+            ..config
+        };
+        let file = temp_env::with_var_unset("POSTGRES_TAG", || {
+            super::parse(&config, &Options::default()).unwrap().file
+        });
+
+        assert_eq!(file.services["db"].image, Some(String::from("postgres:17")));
+    }
+
+    #[test]
+    fn include_paths() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/include-paths/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::parse(&config, &Options::default()).unwrap().file;
+        let source = |service: &str| match &file.services[service].volumes[0].r#type {
+            ServiceVolumeType::Bind(source) => source.clone(),
+            _ => unreachable!(),
+        };
+
+        assert_eq!(
+            source("app"),
+            Path::new("tests/fixtures/include-paths/app/data").absolutize().unwrap()
+        );
+        assert_eq!(
+            source("tools"),
+            Path::new("tests/fixtures/include-paths/cache").absolutize().unwrap()
+        );
+    }
+
+    #[test]
+    fn include_cycle() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/include-cycle/compose.json")],
+            ..Config::default()
+        };
+
+        assert!(super::parse(&config, &Options::default())
+            .unwrap_err()
+            .to_string()
+            .starts_with("Circular reference found in include"));
+    }
+
+    #[test]
+    fn referenced_names() {
+        let mut names = IndexMap::new();
+        super::referenced(
+            &serde_yaml::from_str("image: ${IMAGE:-busybox}:${TAG-${DEFAULT_TAG}}\nuser: $USER\n")
+                .unwrap(),
+            &mut names,
+        );
+
+        assert_eq!(
+            names,
+            indexmap! {
+                String::from("IMAGE") => Some(String::from("busybox")),
+                String::from("TAG") => None,
+                String::from("DEFAULT_TAG") => None,
+                String::from("USER") => None,
+            }
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let base = PathBuf::from("tests/fixtures/reset/compose.yaml");
+        let config = Config {
+            files: vec![base.clone(), PathBuf::from("compose.override.yaml")],
+            ..Config::default()
+        };
+        let sources = [
+            (base.clone(), fs::read_to_string(&base).unwrap()),
+            (
+                PathBuf::from("compose.override.yaml"),
+                String::from(
+                    "services:\n  web:\n    ports: !override\n      - 9090:80\n    environment: !reset {}\n    labels:\n      owner: me\n",
+                ),
+            ),
+        ];
+        let file = super::parse_sources(&config, &sources, &Options::default())
+            .unwrap()
+            .file;
+        let service = &file.services["web"];
+
+        assert_eq!(
+            service.ports.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["9090:80"]
+        );
+        assert!(service.environment.is_empty());
+        assert_eq!(service.labels.keys().collect::<Vec<_>>(), vec!["tier", "owner"]);
+
+        let unnormalized =
+            super::load_unnormalized_sources(&config, &sources, &Options::default()).unwrap();
+        let service = &unnormalized.file["services"]["web"];
+
+        assert_eq!(
+            service["ports"],
+            Value::Sequence(vec![Value::String(String::from("9090:80"))])
+        );
+        assert!(service.get("environment").is_none());
+        assert_eq!(service["labels"]["owner"], Value::String(String::from("me")));
+    }
+
+    #[test]
+    fn sources() {
+        let config = Config {
+            project_name: Some(String::from("memory")),
+            files: vec![PathBuf::from("compose.yaml"), PathBuf::from("compose.override.yaml")],
+            ..Config::default()
+        };
+        let sources = [
+            (
+                PathBuf::from("compose.yaml"),
+                String::from("services:\n  web:\n    image: busybox:${TAG:-latest}\n    ports:\n      - 80:80\n"),
+            ),
+            (
+                PathBuf::from("compose.override.yaml"),
+                String::from("services:\n  web:\n    ports: !reset []\n"),
+            ),
+        ];
+        let file = temp_env::with_var("TAG", None::<&str>, || {
+            super::parse_sources(&config, &sources, &Options::default())
+        })
+        .unwrap()
+        .file;
+
+        assert_eq!(file.name.as_deref(), Some("memory"));
+        assert_eq!(file.services["web"].image.as_deref(), Some("busybox:latest"));
+        assert!(file.services["web"].ports.is_empty());
+
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from("services:\n  web:\n    restart: sometimes\n"),
+        )];
+
+        assert!(super::parse_sources(&config, &sources, &Options::default())
+            .unwrap_err()
+            .to_string()
+            .starts_with("compose.yaml does not follow the Compose specification"));
+    }
+
+    #[test]
+    fn remote() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let responses = [
+                "200 OK\r\nContent-Length: 36\r\n\r\nservices:\n  web:\n    image: busybox\n",
+                "404 Not Found\r\nContent-Length: 0\r\n\r\n",
+            ];
+
+            for (stream, response) in listener.incoming().zip(responses) {
+                let mut stream = stream.unwrap();
+                let mut request = io::BufReader::new(&stream)
+                    .lines()
+                    .map_while(Result::ok)
+                    .take_while(|line| !line.is_empty());
+
+                assert!(request.next().unwrap().starts_with("GET "));
+                request.for_each(drop);
+                write!(stream, "HTTP/1.1 {response}").unwrap();
+            }
+        });
+
+        let config = Config {
+            files: vec![PathBuf::from(format!("http://{address}/compose.yaml"))],
+            ..Config::default()
+        };
+        let file = super::parse(&config, &Options::default()).unwrap().file;
+
+        assert_eq!(file.services["web"].image.as_deref(), Some("busybox"));
+
+        let err = super::parse(&config, &Options::default()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("http://{address}/compose.yaml not found")
+        );
+        assert_eq!(crate::utils::exit_code(&err), 1);
+        assert!(!super::is_remote(Path::new("compose.yaml")));
+    }
+
+    #[test]
+    fn exit_codes() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let code = |source: &str| {
+            let sources = [(PathBuf::from("compose.yaml"), String::from(source))];
+            let err = temp_env::with_var("TAG", None::<&str>, || {
+                super::parse_sources(&config, &sources, &Options::default())
+            })
+            .unwrap_err();
+
+            crate::utils::exit_code(&err)
+        };
+
+        assert_eq!(code("services: [\n"), 2);
+        assert_eq!(code("services:\n  web:\n    restart: sometimes\n"), 3);
+        assert_eq!(code("services:\n  web: {}\n"), 3);
+        assert_eq!(code("services:\n  web:\n    image: busybox:${TAG:?}\n"), 4);
+
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/missing.yaml")],
+            ..Config::default()
+        };
+        let err = super::parse(&config, &Options::default()).unwrap_err();
+
+        assert_eq!(err.to_string(), "tests/fixtures/missing.yaml not found");
+        assert_eq!(crate::utils::exit_code(&err), 1);
+    }
+
+    #[test]
+    fn extensions() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from(
+                "x-common: &common\n  image: busybox\nservices:\n  web:\n    <<: *common\n    x-owner: web-team\n    networks: [front]\n    deploy:\n      x-bogus: 1\nnetworks:\n  front:\n    x-subnet: internal\n",
+            ),
+        )];
+        let parsed = super::parse_sources(&config, &sources, &Options::default())
+        .unwrap();
+
+        assert_eq!(
+            parsed
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![String::from(
+                "Unsupported/unknown properties in compose.yaml: services.web.deploy.x-bogus"
+            )]
+        );
+
+        let file = parsed.file;
+        assert_eq!(
+            file.extensions["x-common"],
+            serde_yaml::from_str::<Value>("image: busybox").unwrap()
+        );
+        assert_eq!(file.services["web"].extensions["x-owner"], "web-team");
+        assert_eq!(file.networks["front"].extensions["x-subnet"], "internal");
+
+        let file = serde_yaml::from_str::<Compose>(&serde_yaml::to_string(&file).unwrap()).unwrap();
+        assert_eq!(file.extensions.keys().collect::<Vec<_>>(), ["x-common"]);
+        assert_eq!(file.services["web"].extensions["x-owner"], "web-team");
+    }
+
+    #[test]
+    fn override_warnings() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml"), PathBuf::from("compose.override.yaml")],
+            ..Config::default()
+        };
+        let base = "services:\n  web:\n    image: busybox\n  db:\n    image: postgres\n";
+        let parse = |config: &Config, other: &str| {
+            let sources = [
+                (PathBuf::from("compose.yaml"), String::from(base)),
+                (PathBuf::from("compose.override.yaml"), String::from(other)),
+            ];
+
+            super::parse_sources(config, &sources, &Options::default())
+            .map(|parsed| {
+                parsed
+                    .warnings
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+            })
+        };
+        let merged = "services:\n  web:\n    environment: [DEBUG=1]\n";
+
+        assert_eq!(parse(&config, merged).unwrap(), Vec::<String>::new());
+        assert_eq!(
+            parse(&config, "services:\n  db: !override\n    image: mariadb\n").unwrap(),
+            vec![String::from(
+                "Service \"db\" from compose.override.yaml replaces the one defined in compose.yaml"
+            )]
+        );
+
+        let config = Config {
+            warn_as_error: true,
+            ..config
+        };
+        assert!(parse(&config, merged).is_ok());
+    }
+
+    #[test]
+    fn warning_locations() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from(
+                "services:\n  web:\n    image: busybox:$TAG\n    imagex: busybox\n    labels:\n      app.${TAG}x: a\n",
+            ),
+        )];
+        let warnings = temp_env::with_var("TAG", None::<&str>, || {
+            super::parse_sources(&config, &sources, &Options::default())
+        })
+        .unwrap()
+        .warnings;
+
+        assert_eq!(
+            warnings,
+            vec![
+                Logged {
+                    message: String::from(
+                        "services.web.image: \"$TAG\" is not set, defaulting to a blank string"
+                    ),
+                    file: Some(String::from("compose.yaml")),
+                    path: Some(String::from("services.web.image")),
+                },
+                Logged {
+                    message: String::from(
+                        "services.web.labels.app.${TAG}x: \"$TAG\" is not set, defaulting to a blank string"
+                    ),
+                    file: Some(String::from("compose.yaml")),
+                    path: Some(String::from("services.web.labels.app.${TAG}x")),
+                },
+                Logged {
+                    message: String::from(
+                        "Unsupported/unknown properties in compose.yaml: services.web.imagex (did you mean \"image\"?)"
+                    ),
+                    file: Some(String::from("compose.yaml")),
+                    path: Some(String::from("services.web.imagex")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from("version: '3'\nservices:\n  web:\n    image: busybox:$TAG\n    scale: 1\n"),
+        )];
+        let parsed = temp_env::with_var("TAG", None::<&str>, || {
+            super::parse_sources(&config, &sources, &Options::default())
+        })
+        .unwrap();
+
+        assert_eq!(
+            parsed
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("services.web.image: \"$TAG\" is not set, defaulting to a blank string"),
+                String::from("`version` is obsolete and ignored, it can be removed from compose.yaml"),
+                String::from("`scale` is deprecated, use the `deploy.replicas` element instead"),
+            ]
+        );
+        assert_eq!(parsed.file.services["web"].image.as_deref(), Some("busybox:"));
+        let config = Config {
+            warn_as_error: true,
+            ..config
+        };
+        let err = temp_env::with_var("TAG", None::<&str>, || {
+            super::parse_sources(&config, &sources, &Options::default())
+        })
+        .unwrap_err();
+
+        assert_eq!(err.to_string(), "3 warnings treated as errors");
+    }
+
+    #[test]
+    fn dependency_conditions() {
+        let errors = |condition: &str| {
+            let file = serde_yaml::from_str::<Compose>(&format!(
+                "services:\n  web:\n    image: busybox\n    networks: [default]\n    depends_on:\n      db:\n        condition: {condition}\n        restart: true\n      cache:\n        condition: {condition}\n  db:\n    image: busybox\n    networks: [default]\nnetworks:\n  default:\n",
+            ))
+            .unwrap();
+
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors("service_healthy"),
+            vec![String::from("Service \"web\" depends on undefined service \"cache\"")]
+        );
+        assert_eq!(
+            errors("service_healty"),
+            vec![
+                String::from("Service \"web\" depends on \"db\" with unknown condition \"service_healty\", expected one of: service_started, service_healthy, service_completed_successfully"),
+                String::from("Service \"web\" depends on undefined service \"cache\""),
+                String::from("Service \"web\" depends on \"cache\" with unknown condition \"service_healty\", expected one of: service_started, service_healthy, service_completed_successfully"),
+            ]
+        );
+        assert!(serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    depends_on:\n      db:\n        condition: service_started\n        restart: sometimes\n",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn unnormalized_extends() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/extends/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::load_unnormalized(
+            &config,
+            &Options {
+                resolve_extends: true,
+                ..Options::default()
+            },
+        )
+        .unwrap()
+        .file;
+
+        for name in ["web", "worker"] {
+            let service = &file["services"][name];
+
+            assert!(service.get("extends").is_none());
+            assert_eq!(service["image"], Value::String(String::from("busybox")));
+            assert_eq!(
+                service["environment"]["ROLE"],
+                Value::String(String::from("web"))
+            );
+        }
+
+        let file = super::load_unnormalized(&config, &Options::default()).unwrap().file;
+
+        assert!(file["services"]["web"].get("extends").is_some());
+    }
+
+    #[test]
+    fn unnormalized_integer_keys() {
+        let config = Config {
+            files: vec![
+                PathBuf::from("tests/fixtures/integer-keys/compose.yaml"),
+                PathBuf::from("tests/fixtures/integer-keys/compose.override.yaml"),
+            ],
+            ..Config::default()
+        };
+        let file = super::load_unnormalized(&config, &Options::default()).unwrap().file;
+
+        assert_eq!(
+            file["services"]["web"]["labels"],
+            serde_yaml::from_str::<Value>("8080: b\n8081: a\n").unwrap()
+        );
+        assert_eq!(
+            super::load(&config, &Options::default()).unwrap().file.services["web"].labels["8080"],
+            "b"
+        );
+    }
+
+    #[test]
+    fn unnormalized_extends_forms() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/extends-forms/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::load_unnormalized(
+            &config,
+            &Options {
+                resolve_extends: true,
+                ..Options::default()
+            },
+        )
+        .unwrap()
+        .file;
+
+        assert_eq!(
+            file["services"]["web"]["environment"],
+            serde_yaml::from_str::<Value>("ROLE: web\nDEBUG: 'false'\n").unwrap()
+        );
+        assert_eq!(
+            file["services"]["web"]["labels"],
+            serde_yaml::from_str::<Value>("8080: b\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn unnormalized() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/profiles/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::load_unnormalized(&config, &Options::default()).unwrap().file;
+
+        assert!(file.get("name").is_none());
+        assert_eq!(
+            file["services"]["web"]
+                .as_mapping()
+                .unwrap()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["image", "depends_on"]
+        );
+    }
+
+    #[test]
+    fn project_name_variable() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/project-name/compose.yaml")],
+            ..Config::default()
+        };
+        let file = temp_env::with_var_unset("COMPOSE_PROJECT_NAME", || {
+            let file = super::parse(&config, &Options::default()).unwrap().file;
+
+            assert!(env::var_os("COMPOSE_PROJECT_NAME").is_none());
+
+            file
+        });
+
+        assert_eq!(
+            file.services["web"].environment["PROJECT"],
+            Some(String::from("myproject"))
+        );
+    }
+
+    #[test]
+    fn dangling() {
+        let config = Config {
+            project_name: Some(String::from("app")),
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from(
+                "services:\n  web:\n    image: busybox\n    networks: [front]\n    volumes: [data:/data]\n    secrets: [token]\n  debug:\n    image: busybox\n    profiles: [debug]\n    volumes: [logs:/logs]\nnetworks:\n  front:\n  back:\nvolumes:\n  data:\n  logs:\n  cache:\nsecrets:\n  token:\n    content: abc\n  key:\n    content: abc\n",
+            ),
+        )];
+        let options = Options {
+            no_prune: true,
+            ..Options::default()
+        };
+
+        let file = super::parse_sources(&config, &sources, &options).unwrap().file;
+        let dangling = super::dangling(&file);
+        assert_eq!(dangling.networks, IndexSet::from(["back", "default"].map(String::from)));
+        assert_eq!(dangling.volumes, IndexSet::from(["logs", "cache"].map(String::from)));
+        assert_eq!(dangling.secrets, IndexSet::from(["key"].map(String::from)));
+
+        let file = super::parse_sources(&config, &sources, &Options::default()).unwrap().file;
+        assert_eq!(file.networks.keys().collect::<Vec<_>>(), ["front"]);
+        assert_eq!(file.volumes.keys().collect::<Vec<_>>(), ["data"]);
+        assert_eq!(file.secrets.keys().collect::<Vec<_>>(), ["token"]);
+    }
+
+    #[test]
+    fn declared_sections() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(
+            PathBuf::from("compose.yaml"),
+            String::from("name: app\nservices:\n  web:\n    image: busybox\nvolumes: {}\n"),
+        )];
+        let parsed = super::parse_sources(&config, &sources, &Options::default()).unwrap();
+
+        assert!(parsed.file.volumes.is_empty());
+        assert_eq!(
+            parsed.sections,
+            IndexSet::from(["name", "services", "volumes"].map(String::from))
+        );
+    }
+
+    #[test]
+    fn project_name_precedence() {
+        let parse = |project_name: Option<&str>, source: &str| {
+            let config = Config {
+                project_name: project_name.map(ToOwned::to_owned),
+                files: vec![PathBuf::from("compose.yaml")],
+                ..Config::default()
+            };
+            let sources = [(PathBuf::from("compose.yaml"), String::from(source))];
+
+            temp_env::with_var("COMPOSE_PROJECT_NAME", Some("from-env"), || {
+                super::parse_sources(&config, &sources, &Options::default())
+            })
+            .unwrap()
+            .file
+            .name
+            .unwrap()
+        };
+        let source = "services:\n  web:\n    image: busybox\n";
+
+        assert_eq!(parse(Some("forced"), &format!("name: declared\n{source}")), "forced");
+        assert_eq!(parse(None, &format!("name: declared\n{source}")), "declared");
+        assert_eq!(parse(None, source), "from-env");
+
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let sources = [(PathBuf::from("compose.yaml"), String::from(source))];
+        let file = temp_env::with_var_unset("COMPOSE_PROJECT_NAME", || {
+            super::parse_sources(&config, &sources, &Options::default())
+        })
+        .unwrap()
+        .file;
+
+        assert_eq!(
+            file.name.unwrap(),
+            env::current_dir()
+                .unwrap()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_ascii_lowercase()
+        );
+    }
+
+    #[test]
+    fn project_name_charset() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let parse = |source: &str| {
+            let sources = [(PathBuf::from("compose.yaml"), String::from(source))];
+
+            temp_env::with_var_unset("COMPOSE_PROJECT_NAME", || {
+                super::parse_sources(&config, &sources, &Options::default())
+            })
+        };
+
+        assert_eq!(
+            parse("name: My Project\nservices:\n  web:\n    image: busybox\n")
+                .unwrap_err()
+                .to_string(),
+            "Project name \"My Project\" must only contain lowercase letters, digits, dashes and underscores, and start with a letter or a digit"
+        );
+        assert_eq!(
+            crate::utils::exit_code(
+                &parse("name: My Project\nservices:\n  web:\n    image: busybox\n").unwrap_err()
+            ),
+            3
+        );
+        assert_eq!(
+            parse("name: 1-my_project\nservices:\n  web:\n    image: busybox\n")
+                .unwrap()
+                .file
+                .name,
+            Some(String::from("1-my_project"))
+        );
+
+        assert_eq!(
+            super::directory_project_name(Path::new("/srv/My .App")).unwrap(),
+            "my__app"
+        );
+        assert_eq!(
+            super::directory_project_name(Path::new("/srv/_-app")).unwrap(),
+            "app"
+        );
+        assert!(super::directory_project_name(Path::new("/srv/...")).is_err());
+    }
+
+    #[test]
+    fn numeric_project_name() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let parse = |name: &str| {
+            let sources = [(
+                PathBuf::from("compose.yaml"),
+                format!("name: {name}\nservices:\n  web:\n    image: busybox\n"),
+            )];
+
+            super::parse_sources(&config, &sources, &Options::default())
+        };
+
+        assert_eq!(
+            parse("123").unwrap_err().to_string(),
+            "Project name 123 in compose.yaml must be a string, it can be quoted to be one"
+        );
+        assert_eq!(
+            parse("1.0").unwrap_err().to_string(),
+            "Project name 1.0 in compose.yaml must be a string, it can be quoted to be one"
+        );
+        assert_eq!(crate::utils::exit_code(&parse("true").unwrap_err()), 3);
+        assert_eq!(parse("'123'").unwrap().file.name, Some(String::from("123")));
+    }
+
+    #[test]
+    fn project_name_conflict() {
+        let config = Config {
+            files: vec![
+                PathBuf::from("tests/fixtures/project-name/compose.yaml"),
+                PathBuf::from("tests/fixtures/project-name/compose.other.yaml"),
+            ],
+            ..Config::default()
+        };
+
+        assert_eq!(
+            super::parse(&config, &Options::default()).unwrap_err().to_string(),
+            "Project name \"other\" from tests/fixtures/project-name/compose.other.yaml conflicts with \"myproject\" defined in tests/fixtures/project-name/compose.yaml"
+        );
+
+        let config = Config {
+            project_name: Some(String::from("forced")),
+            ..config
+        };
+
+        assert_eq!(
+            super::parse(&config, &Options::default()).unwrap().file.name,
+            Some(String::from("forced"))
+        );
+
+        let config = Config {
+            files: vec![
+                PathBuf::from("tests/fixtures/project-name/compose.yaml"),
+                PathBuf::from("tests/fixtures/project-name/compose.same.yaml"),
+            ],
+            ..Config::default()
+        };
+
+        assert_eq!(
+            super::parse(&config, &Options::default()).unwrap().file.name,
+            Some(String::from("myproject"))
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let config = Config {
+            files: vec![PathBuf::from("tests/fixtures/profiles/compose.yaml")],
+            ..Config::default()
+        };
+        let file = super::parse(&config, &Options::default()).unwrap().file;
+
+        assert_eq!(file.services.keys().collect::<Vec<_>>(), vec!["web"]);
+        assert!(file.services["web"].depends_on.is_empty());
+
+        let config = Config {
+            profiles: vec![String::from("debug")],
+            ..config
+        };
+        let file = super::parse(&config, &Options::default()).unwrap().file;
+
+        assert_eq!(
+            file.services.keys().collect::<Vec<_>>(),
+            vec!["web", "debugger"]
+        );
+        assert_eq!(
+            file.services["web"].depends_on.keys().collect::<Vec<_>>(),
+            vec!["debugger"]
+        );
+    }
+
+    #[test]
+    fn validate_all() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  foo:\n    depends_on: [bar]\n  baz:\n    image: busybox\n    networks: [missing]\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"foo\" does not have an image specified"),
+                String::from("Service \"foo\" depends on undefined service \"bar\""),
+                String::from("Service \"foo\" refers to undefined network \"default\""),
+                String::from("Service \"baz\" refers to undefined network \"missing\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn secrets() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    secrets:\n      - db_password\n      - source: api_key\n        target: key\n      - token\n      - source: certificate\nnetworks:\n  default:\nsecrets:\n  db_password:\n    file: ./db_password.txt\n  api_key:\n    external: true\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"web\" refers to undefined secret \"token\""),
+                String::from("Service \"web\" refers to undefined secret \"certificate\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn secret_content() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    secrets: [token, key, cert, api_key]\nnetworks:\n  default:\nsecrets:\n  token:\n    content: abc\n  key:\n    content: abc\n    external: true\n  cert:\n    content: abc\n    file: ./cert.pem\n  api_key:\n    external: true\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Conflicting parameters specified for secret \"key\""),
+                String::from("Conflicting parameters specified for secret \"cert\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn host_network() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    network_mode: host\n    networks: [front]\n    ports: [80:80]\n    extra_hosts: [db:10.0.0.2]\n    links: [db]\n  db:\n    image: busybox\n    networks: [front]\nnetworks:\n  front:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"web\" cannot have networks due to the network mode set"),
+                String::from("Service \"web\" cannot have port mappings due to host network mode"),
+                String::from("Service \"web\" cannot have extra hosts due to host network mode"),
+                String::from("Service \"web\" cannot have links due to host network mode"),
+            ]
+        );
+    }
+
+    #[test]
+    fn external() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default, front]\n    volumes: [data:/data, cache:/cache]\nnetworks:\n  default:\n  front:\n    external: true\n    name: shared_front\nvolumes:\n  data:\n    external: true\n    name: shared_data\n  cache:\n    external: true\n",
+        )
+        .unwrap();
+
+        assert!(validate(&file).is_empty());
+
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default, front, back]\n    volumes: [data:/data, cache:/cache]\nnetworks:\n  default:\n  front:\n    external: true\n    driver: bridge\n  back:\n    external: true\n    name: default\nvolumes:\n  data:\n    external: true\n    name: local_cache\n  cache:\n    name: local_cache\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Conflicting parameters specified for network \"front\""),
+                String::from("External network \"back\" refers to a network created by network \"default\""),
+                String::from("External volume \"data\" refers to a volume created by volume \"cache\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn develop() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    develop:\n      watch:\n        - path: /src\n          action: sync\n          target: /app\n          ignore: [node_modules/]\n        - path: /package.json\n          action: rebuild\n        - path: /config\n          action: sync+restart\nnetworks:\n  default:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![String::from("Service \"web\" needs a target to sync \"/config\" to")]
+        );
+
+        assert!(serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    develop:\n      watch:\n        - path: /src\n          action: copy\n",
+        )
+        .is_err());
+        assert!(serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    develop:\n      watch:\n        - action: rebuild\n",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn platform() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    platform: linux/arm/v7\n  db:\n    image: busybox\n    networks: [default]\n    platform: linux\n  cache:\n    image: busybox\n    networks: [default]\n    platform: linux//amd64\nnetworks:\n  default:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"db\" has an invalid platform \"linux\": expected \"os/arch[/variant]\""),
+                String::from("Service \"cache\" has an invalid platform \"linux//amd64\": expected \"os/arch[/variant]\""),
+            ]
+        );
+        assert_eq!(platform_architecture("linux/amd64").unwrap(), "amd64");
+    }
+
+    #[test]
+    fn links() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    links: [db, db:database, cache, 'db:', 'db:a:b']\n  db:\n    image: busybox\n    networks: [default]\nnetworks:\n  default:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"web\" links to undefined service \"cache\""),
+                String::from("Service \"web\" has an invalid link \"db:\", expected \"service\" or \"service:alias\""),
+                String::from("Service \"web\" has an invalid link \"db:a:b\", expected \"service\" or \"service:alias\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn expose() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    expose: [3000, '3000/udp', 8000-8005, http, '70000', '3000/icmp', '9000-8000']\nnetworks:\n  default:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"web\" has an invalid exposed port \"http\": \"http\" is not a valid port"),
+                String::from("Service \"web\" has an invalid exposed port \"70000\": Port 70000 exceeds 65535"),
+                String::from("Service \"web\" has an invalid exposed port \"3000/icmp\": \"icmp\" is not a valid protocol, expected one of: tcp, udp, sctp"),
+                String::from("Service \"web\" has an invalid exposed port \"9000-8000\": \"9000-8000\" is not a valid port range"),
+            ]
+        );
+    }
+
+    #[test]
+    fn memswap_limit() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    mem_limit: 1g\n    memswap_limit: 512m\n  db:\n    image: busybox\n    networks: [default]\n    mem_limit: 512m\n    memswap_limit: 1gb\n  cache:\n    image: busybox\n    networks: [default]\n    mem_limit: 512m\n    memswap_limit: -1\nnetworks:\n  default:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![String::from(
+                "Service \"web\" has a `memswap_limit` (512 MiB) lower than its `mem_limit` (1 GiB)"
+            )]
+        );
+    }
+
+    #[test]
+    fn ulimits() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    networks: [default]\n    ulimits:\n      nproc: 65535\n      memlock: -1\n      stack: -2\n      nofile:\n        soft: 2000\n        hard: 1000\n      core:\n        soft: 0\n        hard: -1\n      rtprio:\n        soft: -1\n        hard: 10\nnetworks:\n  default:\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"web\" has an invalid ulimit \"stack\": -2 is negative"),
+                String::from("Service \"web\" has an invalid ulimit \"nofile\": the soft limit 2000 exceeds the hard limit 1000"),
+                String::from("Service \"web\" has an invalid ulimit \"rtprio\": the soft limit -1 exceeds the hard limit 10"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dependency_across_files() {
+        let mut file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    depends_on: [database]\nnetworks:\n  default:\n",
+        )
+        .unwrap();
 
-        for network in service.networks.keys() {
-            if !combined_file.networks.contains_key(network) {
-                bail!("Service \"{name}\" refers to undefined network \"{network}\"",);
-            }
-        }
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![String::from(
+                "Service \"web\" depends on undefined service \"database\""
+            )]
+        );
 
-        for volume in service
-            .volumes
-            .iter()
-            .filter_map(|volume| match &volume.r#type {
-                ServiceVolumeType::Volume(source) => source.as_ref(),
-                _ => None,
-            })
-        {
-            if !combined_file.volumes.contains_key(volume) {
-                bail!("Service \"{name}\" refers to undefined volume \"{volume}\"");
-            }
-        }
+        file.merge(
+            serde_yaml::from_str(
+                "services:\n  database:\n    image: postgres\nnetworks:\n  default:\n",
+            )
+            .unwrap(),
+        );
 
-        for secret in &service.secrets {
-            if !combined_file.secrets.contains_key(&secret.source) {
-                bail!(
-                    "Service \"{name}\" refers to undefined secret \"{}\"",
-                    secret.source
-                );
-            }
-        }
+        assert_matches!(validate(&file)[..], []);
     }
 
-    for (name, network) in &combined_file.networks {
-        if network.external.unwrap_or_default()
-            && (network.driver.is_some()
-                || !network.driver_opts.is_empty()
-                || network.enable_ipv6.is_some()
-                || network.ipam.is_some()
-                || network.internal.is_some()
-                || !network.labels.is_empty())
-        {
-            bail!("Conflicting parameters specified for network \"{name}\"");
-        }
+    #[test]
+    fn dependency_cycles() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    depends_on: [db]\n  db:\n    image: busybox\n    depends_on:\n      cache:\n        condition: service_started\n  cache:\n    image: busybox\n    depends_on: [web]\n  worker:\n    image: busybox\n    depends_on: [worker]\n",
+        )
+        .unwrap();
 
-        for label in network.labels.keys() {
-            if label.starts_with("io.podman.compose") {
-                bail!("Network \"name\" cannot have labels starting with \"io.podman.compose\"");
-            }
-        }
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .filter(|err| err.starts_with("Dependency"))
+                .sorted()
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Dependency cycle detected: web -> db -> cache -> web"),
+                String::from("Dependency cycle detected: worker -> worker"),
+            ]
+        );
     }
 
-    for (name, volume) in &combined_file.volumes {
-        if volume.external.unwrap_or_default()
-            && (volume.driver.is_some()
-                || !volume.driver_opts.is_empty()
-                || !volume.labels.is_empty())
-        {
-            bail!("Conflicting parameters specified for volume \"{name}\"");
-        }
+    #[test]
+    fn network_mode_service() {
+        let file = serde_yaml::from_str::<Compose>(
+            "services:\n  web:\n    image: busybox\n    network_mode: service:missing\n  proxy:\n    image: busybox\n    network_mode: service:web\nnetworks:\n  default:\n",
+        )
+        .unwrap();
 
-        for label in volume.labels.keys() {
-            if label.starts_with("io.podman.compose") {
-                bail!("Volume \"name\" cannot have labels starting with \"io.podman.compose\"");
-            }
-        }
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![String::from(
+                "Service \"web\" uses the network stack of undefined service \"missing\""
+            )]
+        );
     }
 
-    for (name, secret) in &combined_file.secrets {
-        if secret.external.unwrap_or_default()
-            && (secret.file.is_some() || secret.environment.is_some())
-        {
-            bail!("Conflicting parameters specified for secret \"{name}\"");
-        }
+    #[test]
+    fn port_conflicts() {
+        let file = serde_yaml::from_str::<Compose>(
+            r#"
+            services:
+              web:
+                image: busybox
+                ports: ["8080:80", "127.0.0.1:9000:90", "53:53/udp"]
+              api:
+                image: busybox
+                ports: ["8079-8081:80-82", "9000:90", "53:53"]
+              proxy:
+                image: busybox
+                ports: ["127.0.0.1:9000:90", "53:53/udp"]
+            networks:
+              default:
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Services \"web\" and \"api\" both publish host port 8080/tcp"),
+                String::from(
+                    "Services \"web\" and \"proxy\" both publish host port 127.0.0.1:9000/tcp"
+                ),
+                String::from("Services \"web\" and \"proxy\" both publish host port 53/udp"),
+            ]
+        );
     }
 
-    let dependencies = combined_file
-        .services
-        .iter()
-        .flat_map(|(to, service)| service.depends_on.keys().map(move |from| (from, to, ())))
-        .collect::<DiGraphMap<_, _>>();
-    let cycles = tarjan_scc(&dependencies)
-        .into_iter()
-        .filter(|component| component.len() > 1)
-        .collect::<Vec<_>>();
+    #[test]
+    fn healthchecks() {
+        let file = serde_yaml::from_str::<Compose>(
+            r#"
+            services:
+              web:
+                image: busybox
+                healthcheck:
+                  test: ["CMD", "true"]
+                  disable: true
+              api:
+                image: busybox
+                healthcheck:
+                  test: ["NONE"]
+                  disable: true
+                  retries: 0
+            networks:
+              default:
+            "#,
+        )
+        .unwrap();
 
-    if !cycles.is_empty() {
-        bail!(
-            "Cycles found: {}",
-            cycles
+        assert_eq!(
+            validate(&file)
                 .into_iter()
-                .map(|component| format!("{} -> {}", component.iter().join(" -> "), component[0]))
-                .join(", ")
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![String::from(
+                "Service \"api\" must have a positive number of healthcheck retries"
+            )]
+        );
+        assert_matches!(
+            serde_yaml::from_str::<Compose>(
+                "services:\n  web:\n    healthcheck:\n      interval: 1x\n"
+            ),
+            Err(_)
         );
     }
 
-    Ok(combined_file)
-}
-
-#[cfg(test)]
-mod tests {
-    use std::path::PathBuf;
+    #[test]
+    fn ports() {
+        let file = serde_yaml::from_str::<Compose>(
+            r#"
+            services:
+              web:
+                image: busybox
+                ports:
+                  - "80"
+                  - "8080:80"
+                  - "127.0.0.1:8081:80"
+                  - "[::1]:8082:80"
+                  - ":8083"
+                  - "8084:80/udp"
+                  - "3000-3005:3000-3005"
+                  - "9000-9001:90"
+                  - 8085
+                  - target: 80
+                    published: 8086
+                    protocol: sctp
+                  - "8080:80:garbage"
+                  - "99999:80"
+                  - "3000-3005:3000-3001"
+                  - "3005-3000:80"
+            networks:
+              default:
+            "#,
+        )
+        .unwrap();
 
-    use assert_matches::assert_matches;
-    use pretty_assertions::assert_eq;
-    use serde_yaml::Value;
-    use test_generator::test_resources;
+        assert_eq!(
+            validate(&file)
+                .into_iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                String::from("Service \"web\" has an invalid port mapping \"8080:80:garbage\": \"garbage\" is not a valid port"),
+                String::from("Service \"web\" has an invalid port mapping \"99999:80\": Port 99999 exceeds 65535"),
+                String::from("Service \"web\" has an invalid port mapping \"3000-3005:3000-3001\": Published and target port ranges must have the same size"),
+                String::from("Service \"web\" has an invalid port mapping \"3005-3000:80\": \"3005-3000\" is not a valid port range"),
+            ]
+        );
+    }
 
-    use super::*;
+    #[test]
+    fn display_stdin() {
+        assert_eq!(display_path(Path::new("-")), "<stdin>");
+        assert_eq!(display_path(Path::new("compose.yaml")), "compose.yaml");
+    }
 
-    #[test_resources("tests/fixtures/**/*.y*ml")]
-    fn parse(resource: &str) {
-        let config = Config {
-            files: vec![PathBuf::from(resource)],
-            ..Config::default()
-        };
+    #[test]
+    fn locate() {
+        let contents = "services:\n  foo:\n    image: busybox\n    command:\n      - echo\n      - ${VAR?}\n";
+        let location = super::locate(
+            contents,
+            &["services", "foo", "command", "1"].map(String::from),
+        );
 
-        assert_matches!(super::parse(&config, false), Ok(_));
+        assert_eq!(
+            location.map(|location| (location.line(), location.column())),
+            Some((6, 9))
+        );
     }
 
     #[test]
     fn simple_named() {
         let result = temp_env::with_var("VAR", Some("woop"), || {
-            interpolate(&Value::String(String::from("$VAR")))
+            interpolate(&Value::String(String::from("$VAR")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("woop"))));
@@ -525,7 +3836,7 @@ mod tests {
     #[test]
     fn simple_named_missing() {
         let result = temp_env::with_var("VAR", None::<&str>, || {
-            interpolate(&Value::String(String::from("pre $VAR post")))
+            interpolate(&Value::String(String::from("pre $VAR post")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("pre  post"))));
@@ -534,7 +3845,7 @@ mod tests {
     #[test]
     fn braced_named() {
         let result = temp_env::with_var("VAR", Some("woop"), || {
-            interpolate(&Value::String(String::from("${VAR}")))
+            interpolate(&Value::String(String::from("${VAR}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("woop"))));
@@ -543,7 +3854,7 @@ mod tests {
     #[test]
     fn braced_named_text() {
         let result = temp_env::with_var("VAR", Some("woop"), || {
-            interpolate(&Value::String(String::from("pre ${VAR} post")))
+            interpolate(&Value::String(String::from("pre ${VAR} post")), &IndexMap::new())
         });
 
         assert_eq!(
@@ -552,10 +3863,164 @@ mod tests {
         );
     }
 
+    #[test]
+    fn variables() {
+        let config = Config {
+            files: vec![PathBuf::from("a.yaml"), PathBuf::from("b.yaml")],
+            ..Config::default()
+        };
+        let result = temp_env::with_vars(
+            [
+                ("COMPOSE_FILE", Some("c.yaml")),
+                ("COMPOSE_PATH_SEPARATOR", Some(";")),
+            ],
+            || {
+                interpolate(
+                    &Value::String(String::from("${COMPOSE_FILE}")),
+                    &super::variables(&config),
+                )
+            },
+        );
+
+        assert_eq!(
+            result.ok(),
+            Some(Value::String(String::from("a.yaml;b.yaml")))
+        );
+    }
+
+    #[test]
+    fn interpolate_error_path() {
+        let source = "services:
+  web:
+    image: busybox
+    environment:
+      DB_PASSWORD: ${DB_PASSWORD:?must be set}
+";
+        let content = serde_yaml::from_str(source).unwrap();
+        let result = temp_env::with_var("DB_PASSWORD", None::<&str>, || {
+            interpolate_file(Path::new("compose.yaml"), source, &content, &IndexMap::new())
+        });
+
+        assert_eq!(
+            result.err().map(|err| err.to_string()),
+            Some(String::from(
+                "compose.yaml:5:20: services.web.environment.DB_PASSWORD: Required variable \"DB_PASSWORD\" is missing a value: must be set"
+            ))
+        );
+
+        let source = "services:
+  web:
+    image: busybox:${TAG:x}
+";
+        let content = serde_yaml::from_str(source).unwrap();
+        let result = interpolate_file(Path::new("compose.yaml"), source, &content, &IndexMap::new());
+
+        assert_eq!(
+            result.err().map(|err| err.to_string()),
+            Some(String::from(
+                "compose.yaml:3:12: services.web.image: Invalid substring offset \"x\" for variable \"TAG\": invalid digit found in string"
+            ))
+        );
+    }
+
+    #[test]
+    fn interpolate_keys() {
+        let value = serde_yaml::from_str::<Value>("${ENV}_web:\n  image: busybox\n").unwrap();
+        let variables = indexmap! { String::from("ENV") => String::from("prod") };
+
+        assert_eq!(
+            interpolate(&value, &variables).ok(),
+            Some(serde_yaml::from_str::<Value>("prod_web:\n  image: busybox\n").unwrap())
+        );
+
+        let value =
+            serde_yaml::from_str::<Value>("${ENV}_web: {}\nprod_web: {}\n").unwrap();
+
+        assert_eq!(
+            interpolate(&value, &variables)
+                .err()
+                .map(|err| err.to_string()),
+            Some(String::from(
+                "Key \"prod_web\" is defined more than once after interpolation"
+            ))
+        );
+    }
+
+    #[test]
+    fn repeated_variables() {
+        let config = Config {
+            files: vec![PathBuf::from("compose.yaml")],
+            ..Config::default()
+        };
+        let source = iter::once(String::from("services:\n"))
+            .chain((0..1000).map(|i| {
+                format!("  web{i}:\n    image: app:${{IMAGE_TAG}}\n    labels:\n      tag: ${{IMAGE_TAG:-none}}\n")
+            }))
+            .collect::<String>();
+        let sources = [(PathBuf::from("compose.yaml"), source)];
+        let file = temp_env::with_var("IMAGE_TAG", Some("1.2.3"), || {
+            super::parse_sources(&config, &sources, &Options::default())
+        })
+        .unwrap()
+        .file;
+
+        assert_eq!(file.services.len(), 1000);
+        assert!(file.services.values().all(|service| {
+            service.image.as_deref() == Some("app:1.2.3") && service.labels["tag"] == "1.2.3"
+        }));
+    }
+
+    #[test]
+    fn variables_overlay() {
+        let config = Config {
+            variables: indexmap! { String::from("TAG") => String::from("v2") },
+            ..Config::default()
+        };
+        let result = temp_env::with_var("TAG", Some("v1"), || {
+            interpolate(
+                &Value::String(String::from("busybox:${TAG}")),
+                &super::variables(&config),
+            )
+        });
+
+        assert_eq!(
+            result.ok(),
+            Some(Value::String(String::from("busybox:v2")))
+        );
+    }
+
+    #[test]
+    fn escaped_named() {
+        let result = temp_env::with_var("VAR", Some("woop"), || {
+            interpolate(&Value::String(String::from("$$VAR")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("$VAR"))));
+    }
+
+    #[test]
+    fn escaped_dollar_text() {
+        let result = interpolate(&Value::String(String::from("pre $$ post")), &IndexMap::new());
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("pre $ post"))));
+    }
+
+    #[test]
+    fn escaped_braced_named() {
+        let result = temp_env::with_var("BRACED", Some("woop"), || {
+            interpolate(&Value::String(String::from("$${BRACED}")), &IndexMap::new())
+        });
+
+        assert_eq!(
+            result.ok(),
+            Some(Value::String(String::from("${BRACED}")))
+        );
+    }
+
     #[test]
     fn default_named() {
         let result = temp_env::with_var("VAR", None::<&str>, || {
-            interpolate(&Value::String(String::from("${VAR-default}")))
+            interpolate(&Value::String(String::from("${VAR-default}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("default"))));
@@ -564,7 +4029,7 @@ mod tests {
     #[test]
     fn no_default_named() {
         let result = temp_env::with_var("VAR", Some("woop"), || {
-            interpolate(&Value::String(String::from("${VAR-default}")))
+            interpolate(&Value::String(String::from("${VAR-default}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("woop"))));
@@ -573,7 +4038,7 @@ mod tests {
     #[test]
     fn default_pattern() {
         let result = temp_env::with_var("DEF", Some("woop"), || {
-            interpolate(&Value::String(String::from("${VAR-$DEF}")))
+            interpolate(&Value::String(String::from("${VAR-$DEF}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("woop"))));
@@ -582,7 +4047,7 @@ mod tests {
     #[test]
     fn default_named_no_empty() {
         let result = temp_env::with_var("VAR", Some(""), || {
-            interpolate(&Value::String(String::from("${VAR:-default}")))
+            interpolate(&Value::String(String::from("${VAR:-default}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("default"))));
@@ -591,7 +4056,7 @@ mod tests {
     #[test]
     fn no_default_named_no_empty() {
         let result = temp_env::with_var("VAR", Some("woop"), || {
-            interpolate(&Value::String(String::from("${VAR:-default}")))
+            interpolate(&Value::String(String::from("${VAR:-default}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("woop"))));
@@ -600,16 +4065,153 @@ mod tests {
     #[test]
     fn default_pattern_no_empty() {
         let result = temp_env::with_vars([("VAR", Some("")), ("DEF", Some("woop"))], || {
-            interpolate(&Value::String(String::from("${VAR:-$DEF}")))
+            interpolate(&Value::String(String::from("${VAR:-$DEF}")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("woop"))));
+    }
+
+    #[test]
+    fn empty_default() {
+        for (value, source) in [
+            (None, "${VAR:-}"),
+            (Some(""), "${VAR:-}"),
+            (None, "${VAR-}"),
+            (Some(""), "${VAR-}"),
+        ] {
+            let result = temp_env::with_var("VAR", value, || {
+                interpolate(&Value::String(String::from(source)), &IndexMap::new())
+            });
+
+            assert_eq!(result.ok(), Some(Value::String(String::new())));
+        }
+
+        let result = temp_env::with_var("VAR", Some("woop"), || {
+            interpolate(&Value::String(String::from("${VAR:-}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::from("woop"))));
     }
 
+    #[test]
+    fn substring_named() {
+        let result = temp_env::with_var("VAR", Some("0123456789abcdef"), || {
+            interpolate(&Value::String(String::from("${VAR:0:7}")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("0123456"))));
+    }
+
+    #[test]
+    fn substring_named_offset_only() {
+        let result = temp_env::with_var("VAR", Some("woop"), || {
+            interpolate(&Value::String(String::from("${VAR:2}")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("op"))));
+    }
+
+    #[test]
+    fn substring_named_negative() {
+        let result = temp_env::with_var("VAR", Some("woop"), || {
+            interpolate(&Value::String(String::from("${VAR: -3:2}")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("oo"))));
+    }
+
+    #[test]
+    fn substring_named_out_of_range() {
+        let result = temp_env::with_var("VAR", Some("woop"), || {
+            interpolate(&Value::String(String::from("${VAR:10:2}")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::new())));
+    }
+
+    #[test]
+    fn substring_named_unicode() {
+        let result = temp_env::with_var("VAR", Some("héllo"), || {
+            interpolate(&Value::String(String::from("${VAR:1:3}")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("éll"))));
+    }
+
+    #[test]
+    fn substring_pattern() {
+        let result = temp_env::with_vars(
+            [("VAR", Some("woop")), ("OFFSET", Some("1")), ("LENGTH", Some("2"))],
+            || interpolate(&Value::String(String::from("${VAR:$OFFSET:${LENGTH}}")), &IndexMap::new()),
+        );
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("oo"))));
+    }
+
+    #[test]
+    fn pattern_first() {
+        let result = temp_env::with_var("IMAGE", Some("docker.io/library/docker.io"), || {
+            interpolate(
+                &Value::String(String::from("${IMAGE/docker.io/registry.local}")),
+                &IndexMap::new(),
+            )
+        });
+
+        assert_eq!(
+            result.ok(),
+            Some(Value::String(String::from("registry.local/library/docker.io")))
+        );
+    }
+
+    #[test]
+    fn case() {
+        let result = temp_env::with_var("TAG", Some("Latest"), || {
+            interpolate(
+                &Value::String(String::from("${TAG^^} ${TAG,,}")),
+                &IndexMap::new(),
+            )
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("LATEST latest"))));
+    }
+
+    #[test]
+    fn case_unset() {
+        let result = temp_env::with_var("TAG", None::<&str>, || {
+            interpolate(&Value::String(String::from("${TAG^^}")), &IndexMap::new())
+        });
+
+        assert_eq!(result.ok(), Some(Value::String(String::new())));
+    }
+
+    #[test]
+    fn pattern_all() {
+        let result = temp_env::with_vars(
+            [("VAR", Some("a/b/c")), ("SEPARATOR", Some("-"))],
+            || interpolate(&Value::String(String::from("${VAR//\\//$SEPARATOR}")), &IndexMap::new()),
+        );
+
+        assert_eq!(result.ok(), Some(Value::String(String::from("a-b-c"))));
+    }
+
+    #[test]
+    fn substring_invalid_offset() {
+        let result = temp_env::with_var("VAR", Some("woop"), || {
+            interpolate(&Value::String(String::from("${VAR:a}")), &IndexMap::new())
+        });
+
+        assert_eq!(
+            result.err().map(|err| err.to_string()),
+            Some(String::from(
+                "Invalid substring offset \"a\" for variable \"VAR\""
+            ))
+        );
+    }
+
     #[test]
     fn error_named() {
         let result = temp_env::with_var("VAR", None::<&str>, || {
-            interpolate(&Value::String(String::from("${VAR?msg}")))
+            interpolate(&Value::String(String::from("${VAR?msg}")), &IndexMap::new())
         });
 
         assert_eq!(
@@ -623,7 +4225,7 @@ mod tests {
     #[test]
     fn error_named_no_empty() {
         let result = temp_env::with_var("VAR", Some(""), || {
-            interpolate(&Value::String(String::from("${VAR:?msg}")))
+            interpolate(&Value::String(String::from("${VAR:?msg}")), &IndexMap::new())
         });
 
         assert_eq!(
@@ -637,7 +4239,7 @@ mod tests {
     #[test]
     fn error_no_message() {
         let result = temp_env::with_var("VAR", None::<&str>, || {
-            interpolate(&Value::String(String::from("${VAR?}")))
+            interpolate(&Value::String(String::from("${VAR?}")), &IndexMap::new())
         });
 
         assert_eq!(
@@ -649,7 +4251,7 @@ mod tests {
     #[test]
     fn error_no_message_no_empty() {
         let result = temp_env::with_var("VAR", Some(""), || {
-            interpolate(&Value::String(String::from("${VAR:?}")))
+            interpolate(&Value::String(String::from("${VAR:?}")), &IndexMap::new())
         });
 
         assert_eq!(
@@ -661,7 +4263,7 @@ mod tests {
     #[test]
     fn replacement_named() {
         let result = temp_env::with_var("VAR", Some(""), || {
-            interpolate(&Value::String(String::from("${VAR+replacement}")))
+            interpolate(&Value::String(String::from("${VAR+replacement}")), &IndexMap::new())
         });
 
         assert_eq!(
@@ -673,7 +4275,7 @@ mod tests {
     #[test]
     fn no_replacement_named() {
         let result = temp_env::with_var("VAR", None::<&str>, || {
-            interpolate(&Value::String(String::from("${VAR+replacement}")))
+            interpolate(&Value::String(String::from("${VAR+replacement}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::new())));
@@ -682,7 +4284,7 @@ mod tests {
     #[test]
     fn replacement_named_no_empty() {
         let result = temp_env::with_var("VAR", Some("woop"), || {
-            interpolate(&Value::String(String::from("${VAR:+replacement}")))
+            interpolate(&Value::String(String::from("${VAR:+replacement}")), &IndexMap::new())
         });
 
         assert_eq!(
@@ -694,7 +4296,7 @@ mod tests {
     #[test]
     fn no_replacement_named_no_empty() {
         let result = temp_env::with_var("VAR", Some(""), || {
-            interpolate(&Value::String(String::from("${VAR:+replacement}")))
+            interpolate(&Value::String(String::from("${VAR:+replacement}")), &IndexMap::new())
         });
 
         assert_eq!(result.ok(), Some(Value::String(String::new())));
@@ -1,10 +1,10 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use nom::{
     branch::alt,
-    bytes::complete::{tag, take_while1},
-    character::complete::{anychar, char},
-    combinator::{all_consuming, cut, eof, map, map_parser, value, verify},
-    multi::{fold_many0, many_till},
+    bytes::complete::{is_not, tag, take_while1},
+    character::complete::{anychar, char, one_of},
+    combinator::{all_consuming, cut, eof, map, map_parser, not, opt, value, verify},
+    multi::{fold_many0, fold_many1, many1, many_till},
     sequence::{delimited, preceded, tuple},
     Finish, IResult,
 };
@@ -21,6 +21,21 @@ pub(crate) enum Var {
     Default(State, Vec<Token>),
     Err(State, Vec<Token>),
     Replace(State, Vec<Token>),
+    Substring(Vec<Token>, Option<Vec<Token>>),
+    Pattern(Occurrence, Vec<Token>, Vec<Token>),
+    Case(Case),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) enum Case {
+    Upper,
+    Lower,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub(crate) enum Occurrence {
+    First,
+    All,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -48,7 +63,13 @@ fn variable(input: &str) -> IResult<&str, Token> {
 fn variable_expanded(input: &str) -> IResult<&str, Token> {
     map_parser(
         delimited(char('{'), take_until_unbalanced('{', '}'), char('}')),
-        cut(alt((parameter, parameter_expanded))),
+        cut(alt((
+            parameter,
+            parameter_expanded,
+            parameter_substring,
+            parameter_pattern,
+            parameter_case,
+        ))),
     )(input)
 }
 
@@ -87,6 +108,86 @@ fn parameter_expanded(input: &str) -> IResult<&str, Token> {
     )(input)
 }
 
+fn index(input: &str) -> IResult<&str, Vec<Token>> {
+    many1(alt((
+        dollar_or_variable,
+        map(is_not("$:"), |string: &str| Token::Str(string.to_string())),
+    )))(input)
+}
+
+fn parameter_substring(input: &str) -> IResult<&str, Token> {
+    map(
+        all_consuming(tuple((
+            name,
+            preceded(tuple((char(':'), not(one_of("-?+")))), index),
+            opt(preceded(char(':'), index)),
+        ))),
+        |(name, offset, length)| {
+            Token::Var(name.to_string(), Some(Var::Substring(offset, length)))
+        },
+    )(input)
+}
+
+// The pattern is matched literally, `\/` being used for a literal slash
+fn pattern(input: &str) -> IResult<&str, Vec<Token>> {
+    fold_many1(
+        alt((
+            dollar_or_variable,
+            value(Token::Str(String::from("/")), tag("\\/")),
+            map(is_not("$/\\"), |string: &str| Token::Str(string.to_string())),
+            map(tag("\\"), |string: &str| Token::Str(string.to_string())),
+        )),
+        Vec::new,
+        |mut tokens, token| {
+            if let (Some(Token::Str(last)), Token::Str(string)) = (tokens.last_mut(), &token) {
+                last.push_str(string);
+            } else {
+                tokens.push(token);
+            }
+
+            tokens
+        },
+    )(input)
+}
+
+fn parameter_pattern(input: &str) -> IResult<&str, Token> {
+    map(
+        all_consuming(tuple((
+            name,
+            alt((tag("//"), tag("/"))),
+            pattern,
+            opt(preceded(char('/'), string)),
+        ))),
+        |(name, separator, pattern, replacement)| {
+            Token::Var(
+                name.to_string(),
+                Some(Var::Pattern(
+                    if separator == "//" {
+                        Occurrence::All
+                    } else {
+                        Occurrence::First
+                    },
+                    pattern,
+                    replacement.unwrap_or_default(),
+                )),
+            )
+        },
+    )(input)
+}
+
+fn parameter_case(input: &str) -> IResult<&str, Token> {
+    map(
+        all_consuming(tuple((
+            name,
+            alt((
+                value(Case::Upper, tag("^^")),
+                value(Case::Lower, tag(",,")),
+            )),
+        ))),
+        |(name, case)| Token::Var(name.to_string(), Some(Var::Case(case))),
+    )(input)
+}
+
 fn string(input: &str) -> IResult<&str, Vec<Token>> {
     fold_many0(
         verify(
@@ -121,7 +222,40 @@ fn string(input: &str) -> IResult<&str, Vec<Token>> {
     )(input)
 }
 
+/// Maximum nesting of braced variables, parsing and evaluation recursing once per level
+pub(crate) const MAX_DEPTH: usize = 64;
+
+fn depth(input: &str) -> usize {
+    let mut chars = input.chars().peekable();
+    let mut depth = 0_usize;
+    let mut max = 0;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '$' => match chars.peek() {
+                Some('$') => {
+                    chars.next();
+                }
+                Some('{') => {
+                    chars.next();
+                    depth += 1;
+                    max = max.max(depth);
+                }
+                _ => {}
+            },
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    max
+}
+
 pub(crate) fn parse(input: &str) -> Result<Vec<Token>> {
+    if depth(input) > MAX_DEPTH {
+        bail!("Interpolation of \"{input}\" exceeds the maximum nesting depth of {MAX_DEPTH}");
+    }
+
     all_consuming(string)(input)
         .finish()
         .map_err(|_| anyhow!("invalid interpolation format for \"{input}\""))
@@ -208,6 +342,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn nesting_depth() {
+        let nested = |depth| {
+            format!(
+                "{}default{}",
+                "${VAR:-".repeat(depth),
+                "}".repeat(depth)
+            )
+        };
+
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(10_000)).err().map(|err| err.to_string()),
+            Some(format!(
+                "Interpolation of \"{}\" exceeds the maximum nesting depth of {MAX_DEPTH}",
+                nested(10_000)
+            ))
+        );
+    }
+
     #[test]
     fn single_dollar_sign() {
         assert_eq!(
@@ -305,6 +459,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn expanded_variable_with_empty_default() {
+        assert_eq!(
+            parse("${foo:-}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Default(State::SetAndNonEmpty, Vec::new()))
+            )])
+        );
+        assert_eq!(
+            parse("${foo-}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Default(State::Set, Vec::new()))
+            )])
+        );
+    }
+
     #[test]
     fn expanded_variable_with_error_if_unset_or_empty() {
         assert_eq!(
@@ -358,6 +530,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn expanded_variable_with_substring() {
+        assert_eq!(
+            parse("${foo:0:7}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Substring(
+                    vec![Token::Str(String::from("0"))],
+                    Some(vec![Token::Str(String::from("7"))])
+                ))
+            )])
+        );
+    }
+
+    #[test]
+    fn expanded_variable_with_substring_offset_only() {
+        assert_eq!(
+            parse("${foo: -3}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Substring(vec![Token::Str(String::from(" -3"))], None))
+            )])
+        );
+    }
+
+    #[test]
+    fn expanded_variable_with_substring_variables() {
+        assert_eq!(
+            parse("${foo:$bar:${baz}}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Substring(
+                    vec![Token::Var(String::from("bar"), None)],
+                    Some(vec![Token::Var(String::from("baz"), None)])
+                ))
+            )])
+        );
+    }
+
+    #[test]
+    fn expanded_variable_with_pattern() {
+        assert_eq!(
+            parse("${foo/docker.io/${bar}}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Pattern(
+                    Occurrence::First,
+                    vec![Token::Str(String::from("docker.io"))],
+                    vec![Token::Var(String::from("bar"), None)]
+                ))
+            )])
+        );
+    }
+
+    #[test]
+    fn expanded_variable_with_global_pattern() {
+        assert_eq!(
+            parse("${foo//a\\/b/c/d}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Pattern(
+                    Occurrence::All,
+                    vec![Token::Str(String::from("a/b"))],
+                    vec![Token::Str(String::from("c/d"))]
+                ))
+            )])
+        );
+    }
+
+    #[test]
+    fn expanded_variable_with_pattern_without_replacement() {
+        assert_eq!(
+            parse("${foo/bar}").ok(),
+            Some(vec![Token::Var(
+                String::from("foo"),
+                Some(Var::Pattern(
+                    Occurrence::First,
+                    vec![Token::Str(String::from("bar"))],
+                    Vec::new()
+                ))
+            )])
+        );
+    }
+
+    #[test]
+    fn expanded_variable_with_case() {
+        assert_eq!(
+            parse("${foo^^}${bar,,}").ok(),
+            Some(vec![
+                Token::Var(String::from("foo"), Some(Var::Case(Case::Upper))),
+                Token::Var(String::from("bar"), Some(Var::Case(Case::Lower)))
+            ])
+        );
+    }
+
     #[test]
     fn nested_expanded_variable() {
         assert_eq!(
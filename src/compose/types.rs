@@ -1,7 +1,16 @@
+//! The normalized model of a Compose file, every field being visible to the rest of the crate.
+//!
+//! The types follow the Compose specification rather than a stable interface of their own: fields
+//! are added, and their representation can change, as more of the specification is supported.
+
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
     convert::Infallible,
     fmt::{self, Display, Formatter},
     hash::{Hash, Hasher},
+    io,
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -9,35 +18,49 @@ use std::{
 use anyhow::{anyhow, bail, Error, Result};
 use byte_unit::Byte;
 use heck::AsKebabCase;
-use humantime::{format_duration, parse_duration};
 use indexmap::{indexmap, IndexMap, IndexSet};
 use path_absolutize::Absolutize;
-use serde::{Deserialize, Serialize};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{
+    de::{DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use serde_with::{
-    formats::PreferMany, serde_as, serde_conv, skip_serializing_none, DefaultOnNull,
-    DisplayFromStr, DurationMicroSeconds, OneOrMany, PickFirst, SetLastValueWins,
+    formats::PreferMany, schemars_1::JsonSchemaAs, serde_as, serde_conv, skip_serializing_none,
+    DefaultOnNull, DisplayFromStr, DurationMicroSeconds, OneOrMany, PickFirst, Same,
+    SetLastValueWins,
 };
-use serde_yaml::Value;
+use serde_yaml::{Mapping, Value};
 
-use crate::utils::{DisplayFromAny, STYLED_WARNING};
+use crate::utils::{regex, DisplayFromAny};
 
 #[skip_serializing_none]
 #[serde_as]
 #[serde_with::apply(
     IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(remote = "Self")]
 pub(crate) struct Compose {
     pub(crate) name: Option<String>,
     pub(crate) version: Option<String>,
+    #[serde_as(as = "Vec<PickFirst<(_, IncludeOrString)>>")]
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub(crate) include: Vec<Include>,
     #[serde_with(skip_apply)]
     #[serde(default)]
     pub(crate) services: IndexMap<String, Service>,
     #[serde_as(as = "IndexMap<_, DefaultOnNull>")]
+    #[schemars(with = "IndexMap<String, Option<Network>>")]
     pub(crate) networks: IndexMap<String, Network>,
     #[serde_as(as = "IndexMap<_, DefaultOnNull>")]
+    #[schemars(with = "IndexMap<String, Option<Volume>>")]
     pub(crate) volumes: IndexMap<String, Volume>,
     pub(crate) secrets: IndexMap<String, Secret>,
+    #[serde(flatten, skip_deserializing)]
+    #[serde_with(skip_apply)]
+    #[schemars(skip)]
+    pub(crate) extensions: IndexMap<String, Value>,
 }
 
 impl Compose {
@@ -61,9 +84,76 @@ impl Compose {
                 .or_insert(service);
         }
 
-        self.networks = other.networks;
-        self.volumes = other.volumes;
-        self.secrets = other.secrets;
+        merge_resources(&mut self.networks, other.networks);
+        merge_resources(&mut self.volumes, other.volumes);
+        merge_resources(&mut self.secrets, other.secrets);
+
+        for (key, other_value) in other.extensions {
+            self.extensions
+                .entry(key)
+                .and_modify(|value| merge(value, other_value.clone()))
+                .or_insert(other_value);
+        }
+    }
+
+    /// Discards a property, so that merging replaces it instead of combining both values
+    pub(crate) fn reset(&mut self, path: &[String]) {
+        if let Some((section, rest)) = path.split_first() {
+            match section.as_str() {
+                "services" => reset_resources(&mut self.services, rest),
+                "networks" => reset_resources(&mut self.networks, rest),
+                "volumes" => reset_resources(&mut self.volumes, rest),
+                "secrets" => reset_resources(&mut self.secrets, rest),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn reset_resources<T>(resources: &mut IndexMap<String, T>, path: &[String])
+where
+    T: Serialize + DeserializeOwned,
+{
+    match path {
+        [] => resources.clear(),
+        [name] => {
+            resources.shift_remove(name);
+        }
+        [name, rest @ ..] => {
+            if let Some(resource) = resources.get_mut(name) {
+                let mut value = serde_yaml::to_value(&*resource).unwrap();
+                let mut parent = &mut value;
+
+                for key in &rest[..rest.len() - 1] {
+                    match parent.get_mut(key.as_str()) {
+                        Some(value) => parent = value,
+                        None => return,
+                    }
+                }
+
+                if let Some(mapping) = parent.as_mapping_mut() {
+                    mapping.remove(rest[rest.len() - 1].as_str());
+                }
+
+                *resource = serde_yaml::from_value(value).unwrap();
+            }
+        }
+    }
+}
+
+fn merge_resources<T>(base: &mut IndexMap<String, T>, other: IndexMap<String, T>)
+where
+    T: Serialize + DeserializeOwned,
+{
+    for (name, resource) in other {
+        if let Some(base) = base.get_mut(&name) {
+            let mut value = serde_yaml::to_value(&*base).unwrap();
+            merge(&mut value, serde_yaml::to_value(resource).unwrap());
+
+            *base = serde_yaml::from_value(value).unwrap();
+        } else {
+            base.insert(name, resource);
+        }
     }
 }
 
@@ -74,7 +164,8 @@ impl Compose {
     IndexSet => #[serde(skip_serializing_if = "IndexSet::is_empty", default)],
     Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(remote = "Self")]
 pub(crate) struct Service {
     pub(crate) blkio_config: Option<BlkioConfig>,
     pub(crate) cap_add: Vec<String>,
@@ -96,8 +187,10 @@ pub(crate) struct Service {
     pub(crate) cpus: Option<f64>,
     pub(crate) cpuset: Option<String>,
     #[serde_as(as = "PickFirst<(_, IndexMap<DisplayFromAny, _>, DependsOnVec)>")]
+    #[schemars(with = "serde_with::Schema<IndexMap<String, Dependency>, PickFirst<(Same, DependsOnVec)>>")]
     pub(crate) depends_on: IndexMap<String, Dependency>,
     pub(crate) deploy: Option<DeployConfig>,
+    pub(crate) develop: Option<Development>,
     pub(crate) device_cgroup_rules: Vec<String>,
     #[serde_as(as = "SetLastValueWins<DeviceOrString>")]
     pub(crate) devices: IndexSet<Device>,
@@ -108,16 +201,25 @@ pub(crate) struct Service {
     pub(crate) dns_search: Vec<String>,
     #[serde_as(as = "PickFirst<(_, CommandOrString)>")]
     pub(crate) entrypoint: Vec<String>,
-    #[serde_as(as = "OneOrMany<AbsPathBuf, PreferMany>")]
-    pub(crate) env_file: Vec<PathBuf>,
+    #[serde_as(as = "OneOrMany<PickFirst<(_, EnvFileOrString)>, PreferMany>")]
+    pub(crate) env_file: Vec<EnvFile>,
     #[serde_as(
         as = "PickFirst<(_, IndexMap<DisplayFromAny, Option<DisplayFromAny>>, MappingWithEqualsNull)>"
     )]
+    #[schemars(
+        with = "serde_with::Schema<HashMap<String, Option<String>>, PickFirst<(HashMap<Same, Option<DisplayFromAny>>, MappingWithEqualsNull)>>"
+    )]
     pub(crate) environment: IndexMap<String, Option<String>>,
+    #[serde_as(as = "Vec<DisplayFromAny>")]
     pub(crate) expose: Vec<String>,
+    #[serde_as(as = "Option<PickFirst<(_, ExtendsOrString)>>")]
+    pub(crate) extends: Option<Extends>,
     #[serde_as(
         as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithColonEmpty)>"
     )]
+    #[schemars(
+        with = "serde_with::Schema<HashMap<String, String>, PickFirst<(HashMap<Same, DisplayFromAny>, MappingWithColonEmpty)>>"
+    )]
     pub(crate) extra_hosts: IndexMap<String, String>,
     pub(crate) group_add: Vec<String>,
     pub(crate) healthcheck: Option<Healthcheck>,
@@ -128,14 +230,25 @@ pub(crate) struct Service {
     #[serde_as(
         as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithEqualsEmpty)>"
     )]
+    #[schemars(
+        with = "serde_with::Schema<HashMap<String, String>, PickFirst<(HashMap<Same, DisplayFromAny>, MappingWithEqualsEmpty)>>"
+    )]
     pub(crate) labels: IndexMap<String, String>,
+    pub(crate) links: Vec<String>,
     pub(crate) logging: Option<Logging>,
     pub(crate) mac_address: Option<String>,
+    #[serde_as(as = "Option<PickFirst<(BytesOrU64, BytesWithSuffix)>>")]
+    #[schemars(with = "Option<ByteSize>")]
     pub(crate) mem_limit: Option<Byte>,
+    #[serde_as(as = "Option<PickFirst<(BytesOrU64, BytesWithSuffix)>>")]
+    #[schemars(with = "Option<ByteSize>")]
     pub(crate) mem_reservation: Option<Byte>,
     pub(crate) mem_swappiness: Option<i32>,
     pub(crate) memswap_limit: Option<SwapLimit>,
     #[serde_as(as = "PickFirst<(_, IndexMap<DisplayFromAny, _>, NetworksVec)>")]
+    #[schemars(
+        with = "serde_with::Schema<IndexMap<String, Option<ServiceNetwork>>, PickFirst<(Same, NetworksVec)>>"
+    )]
     #[serde_with(skip_apply)]
     #[serde(default = "default_service_networks")]
     pub(crate) networks: IndexMap<String, Option<ServiceNetwork>>,
@@ -151,6 +264,7 @@ pub(crate) struct Service {
     pub(crate) profiles: Vec<String>,
     pub(crate) pull_policy: Option<PullPolicy>,
     pub(crate) read_only: Option<bool>,
+    #[serde_as(as = "Option<RestartPolicyOrString>")]
     pub(crate) restart: Option<RestartPolicy>,
     pub(crate) runtime: Option<String>,
     pub(crate) scale: Option<u32>,
@@ -158,6 +272,8 @@ pub(crate) struct Service {
     pub(crate) secrets: IndexSet<FileReference>,
     #[serde_as(as = "SecurityOptVec")]
     pub(crate) security_opt: Vec<(String, Option<String>)>,
+    #[serde_as(as = "Option<PickFirst<(BytesOrU64, BytesWithSuffix)>>")]
+    #[schemars(with = "Option<ByteSize>")]
     pub(crate) shm_size: Option<Byte>,
     pub(crate) stdin_open: Option<bool>,
     #[serde_as(as = "Option<DurationWithSuffix>")]
@@ -167,6 +283,9 @@ pub(crate) struct Service {
     #[serde_as(
         as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithEqualsNoNull)>"
     )]
+    #[schemars(
+        with = "serde_with::Schema<HashMap<String, String>, PickFirst<(HashMap<Same, DisplayFromAny>, MappingWithEqualsNoNull)>>"
+    )]
     pub(crate) sysctls: IndexMap<String, String>,
     #[serde_as(as = "OneOrMany<_, PreferMany>")]
     pub(crate) tmpfs: Vec<PathBuf>,
@@ -179,6 +298,10 @@ pub(crate) struct Service {
     pub(crate) volumes: IndexSet<ServiceVolume>,
     pub(crate) volumes_from: Vec<String>,
     pub(crate) working_dir: Option<PathBuf>,
+    #[serde(flatten, skip_deserializing)]
+    #[serde_with(skip_apply)]
+    #[schemars(skip)]
+    pub(crate) extensions: IndexMap<String, Value>,
 }
 
 fn default_service_networks() -> IndexMap<String, Option<ServiceNetwork>> {
@@ -187,16 +310,30 @@ fn default_service_networks() -> IndexMap<String, Option<ServiceNetwork>> {
     }
 }
 
-fn merge(base: &mut Value, other: Value) {
+/// Merges two Compose values following the override rules: mappings are merged, sequences are
+/// appended, and scalars, `command` and `entrypoint` are replaced. Sequences deserialized as sets,
+/// like `volumes` or `devices`, then keep the last entry for each target. Tagging a property with
+/// `!reset` or `!override` in a later file discards the previous value instead.
+pub(crate) fn merge(base: &mut Value, other: Value) {
     match (base, other) {
         (base @ Value::Mapping(_), Value::Mapping(other)) => {
             let base = base.as_mapping_mut().unwrap();
 
-            for (key, other_value) in other {
+            for (key, mut other_value) in other {
+                if let (Some(name), Some(value)) = (key.as_str(), base.get_mut(&key)) {
+                    if value.is_sequence() != other_value.is_sequence() {
+                        to_mapping(name, value);
+                        to_mapping(name, &mut other_value);
+                    }
+                }
+
                 base.entry(key.clone())
-                    .and_modify(|value| match key.as_str().unwrap() {
-                        "command" | "entrypoint" => *value = other_value.clone(),
-                        _ => merge(value, other_value.clone()),
+                    .and_modify(|value| {
+                        if matches!(key.as_str(), Some("command" | "entrypoint")) {
+                            *value = other_value.clone();
+                        } else {
+                            merge(value, other_value.clone());
+                        }
                     })
                     .or_insert(other_value);
             }
@@ -208,6 +345,44 @@ fn merge(base: &mut Value, other: Value) {
     }
 }
 
+/// Converts the list form of the service elements that can also be written as a mapping, so that
+/// both forms can be merged
+fn to_mapping(name: &str, value: &mut Value) {
+    let Value::Sequence(items) = value else {
+        return;
+    };
+    let items = items.iter().filter_map(Value::as_str);
+
+    let mapping = match name {
+        "environment" | "labels" | "annotations" | "sysctls" => items
+            .map(|item| match item.split_once('=') {
+                Some((key, value)) => (Value::from(key), Value::from(value)),
+                None if name == "environment" => (Value::from(item), Value::Null),
+                None => (Value::from(item), Value::from("")),
+            })
+            .collect(),
+        "extra_hosts" => items
+            .filter_map(|item| item.split_once('=').or_else(|| item.split_once(':')))
+            .map(|(host, ip)| (Value::from(host), Value::from(ip)))
+            .collect(),
+        "depends_on" => items
+            .map(|item| {
+                (
+                    Value::from(item),
+                    Value::Mapping(Mapping::from_iter([(
+                        Value::from("condition"),
+                        Value::from("service_started"),
+                    )])),
+                )
+            })
+            .collect(),
+        "networks" => items.map(|item| (Value::from(item), Value::Null)).collect(),
+        _ => return,
+    };
+
+    *value = Value::Mapping(mapping);
+}
+
 impl Service {
     pub(crate) fn merge(&mut self, other: &Self) {
         let mut value = serde_yaml::to_value(&self).unwrap();
@@ -269,11 +444,11 @@ impl Service {
             args.extend([String::from("--cap-drop"), cap_drop]);
         }
 
-        if let Some(cgroup) = self.cgroup.as_ref().cloned() {
+        if let Some(cgroup) = self.cgroup.clone() {
             args.extend([String::from("--cgroupns"), cgroup]);
         }
 
-        if let Some(cgroup_parent) = self.cgroup_parent.as_ref().cloned() {
+        if let Some(cgroup_parent) = self.cgroup_parent.clone() {
             args.extend([String::from("--cgroup-parent"), cgroup_parent]);
         }
 
@@ -309,7 +484,7 @@ impl Service {
             args.extend([String::from("--cpu-shares"), cpu_shares.to_string()]);
         }
 
-        if let Some(cpuset) = self.cpuset.as_ref().cloned() {
+        if let Some(cpuset) = self.cpuset.clone() {
             args.extend([String::from("--cpuset-cpus"), cpuset]);
         }
 
@@ -397,13 +572,6 @@ impl Service {
             ]);
         }
 
-        for env_file in &self.env_file {
-            args.extend([
-                String::from("--env-file"),
-                env_file.to_string_lossy().to_string(),
-            ]);
-        }
-
         for (key, value) in &self.environment {
             args.extend([
                 String::from("--env"),
@@ -438,21 +606,21 @@ impl Service {
             if let Some(interval) = healthcheck.interval {
                 args.extend([
                     String::from("--health-interval"),
-                    interval.as_secs().to_string(),
+                    format_duration(interval),
                 ]);
             }
 
             if let Some(timeout) = healthcheck.timeout {
                 args.extend([
                     String::from("--health-timeout"),
-                    timeout.as_secs().to_string(),
+                    format_duration(timeout),
                 ]);
             }
 
             if let Some(start_period) = healthcheck.start_period {
                 args.extend([
                     String::from("--health-start-period"),
-                    start_period.as_secs().to_string(),
+                    format_duration(start_period),
                 ]);
             }
 
@@ -465,7 +633,7 @@ impl Service {
             }
         }
 
-        if let Some(hostname) = self.hostname.as_ref().cloned() {
+        if let Some(hostname) = self.hostname.clone() {
             args.extend([String::from("--hostname"), hostname]);
         }
 
@@ -473,7 +641,7 @@ impl Service {
             args.push(String::from("--init"));
         }
 
-        if let Some(ipc) = self.ipc.as_ref().cloned() {
+        if let Some(ipc) = self.ipc.clone() {
             args.extend([String::from("--ipc"), ipc]);
         }
 
@@ -482,7 +650,7 @@ impl Service {
         }
 
         if let Some(logging) = &self.logging {
-            if let Some(driver) = logging.driver.as_ref().cloned() {
+            if let Some(driver) = logging.driver.clone() {
                 args.extend([String::from("--log-driver"), driver]);
             }
 
@@ -502,7 +670,7 @@ impl Service {
             args.extend([String::from("--memory-swap"), memswap_limit.to_string()]);
         }
 
-        if let Some(network_mode) = self.network_mode.as_ref().cloned() {
+        if let Some(network_mode) = self.network_mode.clone() {
             args.extend([String::from("--network"), network_mode]);
         }
 
@@ -514,11 +682,11 @@ impl Service {
             args.extend([String::from("--oom-score-adj"), oom_score_adj.to_string()]);
         }
 
-        if let Some(pid) = self.pid.as_ref().cloned() {
+        if let Some(pid) = self.pid.clone() {
             args.extend([String::from("--pid"), pid]);
         }
 
-        if let Some(platform) = self.platform.as_ref().cloned() {
+        if let Some(platform) = self.platform.clone() {
             args.extend([String::from("--platform"), platform]);
         }
 
@@ -538,7 +706,7 @@ impl Service {
             args.extend([String::from("--restart"), restart.to_string()]);
         }
 
-        if let Some(runtime) = self.runtime.as_ref().cloned() {
+        if let Some(runtime) = self.runtime.clone() {
             global_args.extend([String::from("--runtime"), runtime]);
         }
 
@@ -568,7 +736,7 @@ impl Service {
             ]);
         }
 
-        if let Some(stop_signal) = self.stop_signal.as_ref().cloned() {
+        if let Some(stop_signal) = self.stop_signal.clone() {
             args.extend([String::from("--stop-signal"), stop_signal]);
         }
 
@@ -592,15 +760,15 @@ impl Service {
             args.extend([String::from("--ulimit"), format!("{key}={value}")]);
         }
 
-        if let Some(user) = self.user.as_ref().cloned() {
+        if let Some(user) = self.user.clone() {
             args.extend([String::from("--user"), user]);
         }
 
-        if let Some(userns_mode) = self.userns_mode.as_ref().cloned() {
+        if let Some(userns_mode) = self.userns_mode.clone() {
             args.extend([String::from("--userns"), userns_mode]);
         }
 
-        if let Some(uts) = self.uts.as_ref().cloned() {
+        if let Some(uts) = self.uts.clone() {
             args.extend([String::from("--uts"), uts]);
         }
 
@@ -615,7 +783,7 @@ impl Service {
             ]);
         }
 
-        if let Some(image) = self.image.as_ref().cloned() {
+        if let Some(image) = self.image.clone() {
             args.push(image);
         }
 
@@ -631,7 +799,7 @@ impl Service {
 #[serde_with::apply(
     Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct BlkioConfig {
     pub(crate) weight: Option<u16>,
     pub(crate) weight_device: Vec<WeightDevice>,
@@ -642,7 +810,7 @@ pub(crate) struct BlkioConfig {
 }
 
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct WeightDevice {
     #[serde_as(as = "AbsPathBuf")]
     pub(crate) path: PathBuf,
@@ -656,10 +824,12 @@ impl Display for WeightDevice {
 }
 
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct ThrottleDevice {
     #[serde_as(as = "AbsPathBuf")]
     pub(crate) path: PathBuf,
+    #[serde_as(as = "PickFirst<(BytesOrU64, BytesWithSuffix)>")]
+    #[schemars(with = "ByteSize")]
     pub(crate) rate: Byte,
 }
 
@@ -669,12 +839,14 @@ impl Display for ThrottleDevice {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct Dependency {
     pub(crate) condition: Condition,
+    pub(crate) restart: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 pub(crate) enum Condition {
     #[serde(rename = "service_started")]
     Started,
@@ -682,17 +854,53 @@ pub(crate) enum Condition {
     Healthy,
     #[serde(rename = "service_completed_successfully")]
     CompletedSuccessfully,
+    // Deserialized to report the services involved rather than a bare unknown variant
+    #[serde(untagged)]
+    #[schemars(skip)]
+    Unknown(String),
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct DeployConfig {
     pub(crate) replicas: Option<u32>,
     pub(crate) resources: Option<Resources>,
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub(crate) struct Development {
+    pub(crate) watch: Vec<WatchRule>,
+}
+
+#[skip_serializing_none]
+#[serde_as]
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub(crate) struct WatchRule {
+    #[serde_as(as = "AbsPathBuf")]
+    pub(crate) path: PathBuf,
+    pub(crate) action: WatchAction,
+    pub(crate) target: Option<PathBuf>,
+    pub(crate) ignore: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum WatchAction {
+    Sync,
+    Rebuild,
+    #[serde(rename = "sync+restart")]
+    SyncRestart,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct Resources {
     pub(crate) limits: Option<Resource>,
     pub(crate) reservations: Option<Resource>,
@@ -700,17 +908,19 @@ pub(crate) struct Resources {
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct Resource {
     #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
     pub(crate) cpus: Option<f64>,
+    #[serde_as(as = "Option<PickFirst<(BytesOrU64, BytesWithSuffix)>>")]
+    #[schemars(with = "Option<ByteSize>")]
     pub(crate) memory: Option<Byte>,
     pub(crate) pids: Option<i32>,
 }
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct Device {
     #[serde_as(as = "AbsPathBuf")]
     pub(crate) source: PathBuf,
@@ -748,12 +958,45 @@ impl Display for Device {
     }
 }
 
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub(crate) struct EnvFile {
+    pub(crate) path: PathBuf,
+    #[serde(default = "default_required")]
+    pub(crate) required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub(crate) struct Extends {
+    pub(crate) service: String,
+    pub(crate) file: Option<PathBuf>,
+}
+
 #[skip_serializing_none]
 #[serde_as]
 #[serde_with::apply(
     Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub(crate) struct Include {
+    #[serde_with(skip_apply)]
+    #[serde_as(as = "OneOrMany<_, PreferMany>")]
+    pub(crate) path: Vec<PathBuf>,
+    #[serde_as(as = "OneOrMany<_, PreferMany>")]
+    pub(crate) env_file: Vec<PathBuf>,
+    pub(crate) project_directory: Option<PathBuf>,
+}
+
+#[skip_serializing_none]
+#[serde_as]
+#[serde_with::apply(
+    Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
+)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct Healthcheck {
     #[serde_as(as = "PickFirst<(_, CommandOrString)>")]
     pub(crate) test: Vec<String>,
@@ -771,16 +1014,21 @@ pub(crate) struct Healthcheck {
 #[serde_with::apply(
     IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct Logging {
     pub(crate) driver: Option<String>,
     pub(crate) options: IndexMap<String, String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde_as]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(untagged)]
 pub(crate) enum SwapLimit {
-    Limited(Byte),
+    Limited(
+        #[serde_as(as = "PickFirst<(BytesOrU64, BytesWithSuffix)>")]
+        #[schemars(with = "ByteSize")]
+        Byte,
+    ),
     Unlimited(i8),
 }
 
@@ -798,7 +1046,7 @@ impl Display for SwapLimit {
 #[serde_with::apply(
     Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct ServiceNetwork {
     #[serde_as(as = "Vec<DisplayFromAny>")]
     pub(crate) aliases: Vec<String>,
@@ -834,7 +1082,7 @@ impl Display for ServiceNetwork {
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub(crate) struct Port {
     #[serde_as(as = "DisplayFromAny")]
     pub(crate) target: String,
@@ -874,7 +1122,7 @@ impl Display for Port {
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Clone, Debug)]
 #[serde(rename_all = "lowercase")]
 pub(crate) enum PullPolicy {
     Always,
@@ -889,24 +1137,29 @@ impl Display for PullPolicy {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-#[serde(rename_all = "kebab-case")]
+#[derive(Clone, Debug)]
 pub(crate) enum RestartPolicy {
     No,
     Always,
-    OnFailure,
+    OnFailure(Option<u32>),
     UnlessStopped,
 }
 
 impl Display for RestartPolicy {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", AsKebabCase(format!("{self:?}")))
+        match self {
+            RestartPolicy::No => write!(f, "no"),
+            RestartPolicy::Always => write!(f, "always"),
+            RestartPolicy::OnFailure(None) => write!(f, "on-failure"),
+            RestartPolicy::OnFailure(Some(retries)) => write!(f, "on-failure:{retries}"),
+            RestartPolicy::UnlessStopped => write!(f, "unless-stopped"),
+        }
     }
 }
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub(crate) struct FileReference {
     #[serde_as(as = "DisplayFromAny")]
     pub(crate) source: String,
@@ -958,7 +1211,7 @@ impl Display for FileReference {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(untagged)]
 pub(crate) enum ResourceLimit {
     Single(i32),
@@ -976,7 +1229,7 @@ impl Display for ResourceLimit {
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct ServiceVolume {
     #[serde(flatten)]
     pub(crate) r#type: ServiceVolumeType,
@@ -1023,11 +1276,11 @@ impl Display for ServiceVolume {
                 volume.insert(0, source.to_string_lossy().to_string());
 
                 if let Some(bind) = &self.bind {
-                    if let Some(propagation) = bind.propagation.as_ref().cloned() {
+                    if let Some(propagation) = bind.propagation.clone() {
                         options.push(propagation);
                     }
 
-                    if let Some(selinux) = bind.selinux.as_ref().cloned() {
+                    if let Some(selinux) = bind.selinux.clone() {
                         options.push(selinux);
                     }
                 }
@@ -1059,7 +1312,7 @@ impl Display for ServiceVolume {
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 #[serde(tag = "type", content = "source", rename_all = "lowercase")]
 pub(crate) enum ServiceVolumeType {
     Volume(#[serde_as(as = "Option<DisplayFromAny>")] Option<String>),
@@ -1068,14 +1321,14 @@ pub(crate) enum ServiceVolumeType {
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct ServiceVolumeVolume {
     pub(crate) nocopy: Option<bool>,
 }
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
 pub(crate) struct ServiceVolumeBind {
     #[serde_as(as = "Option<DisplayFromAny>")]
     pub(crate) propagation: Option<String>,
@@ -1086,8 +1339,10 @@ pub(crate) struct ServiceVolumeBind {
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct ServiceVolumeTmpfs {
+    #[serde_as(as = "Option<PickFirst<(BytesOrU64, BytesWithSuffix)>>")]
+    #[schemars(with = "Option<ByteSize>")]
     pub(crate) size: Option<Byte>,
     #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
     pub(crate) mode: Option<u32>,
@@ -1098,7 +1353,8 @@ pub(crate) struct ServiceVolumeTmpfs {
 #[serde_with::apply(
     IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(remote = "Self")]
 pub(crate) struct Network {
     pub(crate) name: Option<String>,
     pub(crate) driver: Option<String>,
@@ -1109,8 +1365,15 @@ pub(crate) struct Network {
     #[serde_as(
         as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithEqualsEmpty)>"
     )]
+    #[schemars(
+        with = "serde_with::Schema<HashMap<String, String>, PickFirst<(HashMap<Same, DisplayFromAny>, MappingWithEqualsEmpty)>>"
+    )]
     pub(crate) labels: IndexMap<String, String>,
     pub(crate) external: Option<bool>,
+    #[serde(flatten, skip_deserializing)]
+    #[serde_with(skip_apply)]
+    #[schemars(skip)]
+    pub(crate) extensions: IndexMap<String, Value>,
 }
 
 impl Network {
@@ -1118,7 +1381,7 @@ impl Network {
         let mut args = Vec::new();
 
         if !self.external.unwrap_or_default() {
-            if let Some(driver) = self.driver.as_ref().cloned() {
+            if let Some(driver) = self.driver.clone() {
                 args.extend([String::from("--driver"), driver]);
             }
 
@@ -1131,20 +1394,20 @@ impl Network {
             }
 
             if let Some(ipam) = &self.ipam {
-                if let Some(driver) = ipam.driver.as_ref().cloned() {
+                if let Some(driver) = ipam.driver.clone() {
                     args.extend([String::from("--ipam-driver"), driver]);
                 }
 
                 for config in &ipam.config {
-                    if let Some(subnet) = config.subnet.as_ref().cloned() {
+                    if let Some(subnet) = config.subnet.clone() {
                         args.extend([String::from("--subnet"), subnet]);
                     }
 
-                    if let Some(ip_range) = config.ip_range.as_ref().cloned() {
+                    if let Some(ip_range) = config.ip_range.clone() {
                         args.extend([String::from("--ip-range"), ip_range]);
                     }
 
-                    if let Some(gateway) = config.gateway.as_ref().cloned() {
+                    if let Some(gateway) = config.gateway.clone() {
                         args.extend([String::from("--gateway"), gateway]);
                     }
                 }
@@ -1169,14 +1432,14 @@ impl Network {
 #[serde_with::apply(
     Vec => #[serde(skip_serializing_if = "Vec::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct IpamConfig {
     pub(crate) driver: Option<String>,
     pub(crate) config: Vec<IpamPool>,
 }
 
 #[skip_serializing_none]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
 pub(crate) struct IpamPool {
     pub(crate) subnet: Option<String>,
     pub(crate) ip_range: Option<String>,
@@ -1188,7 +1451,8 @@ pub(crate) struct IpamPool {
 #[serde_with::apply(
     IndexMap => #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
 )]
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(remote = "Self")]
 pub(crate) struct Volume {
     pub(crate) name: Option<String>,
     pub(crate) driver: Option<String>,
@@ -1197,7 +1461,14 @@ pub(crate) struct Volume {
     #[serde_as(
         as = "PickFirst<(_, IndexMap<DisplayFromAny, DisplayFromAny>, MappingWithEqualsEmpty)>"
     )]
+    #[schemars(
+        with = "serde_with::Schema<HashMap<String, String>, PickFirst<(HashMap<Same, DisplayFromAny>, MappingWithEqualsEmpty)>>"
+    )]
     pub(crate) labels: IndexMap<String, String>,
+    #[serde(flatten, skip_deserializing)]
+    #[serde_with(skip_apply)]
+    #[schemars(skip)]
+    pub(crate) extensions: IndexMap<String, Value>,
 }
 
 impl Volume {
@@ -1205,7 +1476,7 @@ impl Volume {
         let mut args = Vec::new();
 
         if !self.external.unwrap_or_default() {
-            if let Some(driver) = self.driver.as_ref().cloned() {
+            if let Some(driver) = self.driver.clone() {
                 args.extend([String::from("--driver"), driver]);
             }
 
@@ -1226,13 +1497,18 @@ impl Volume {
 
 #[skip_serializing_none]
 #[serde_as]
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(remote = "Self")]
 pub(crate) struct Secret {
     pub(crate) name: Option<String>,
     #[serde_as(as = "Option<AbsPathBuf>")]
     pub(crate) file: Option<PathBuf>,
     pub(crate) environment: Option<String>,
+    pub(crate) content: Option<String>,
     pub(crate) external: Option<bool>,
+    #[serde(flatten, skip_deserializing)]
+    #[schemars(skip)]
+    pub(crate) extensions: IndexMap<String, Value>,
 }
 
 impl Secret {
@@ -1246,8 +1522,10 @@ impl Secret {
 
             args.push(self.name.clone().unwrap());
 
-            if let Some(environment) = self.environment.as_ref().cloned() {
+            if let Some(environment) = self.environment.clone() {
                 args.push(environment);
+            } else if self.content.is_some() {
+                args.push(String::from("-"));
             } else if let Some(file) = &self.file {
                 args.push(file.to_string_lossy().to_string());
             }
@@ -1257,15 +1535,33 @@ impl Secret {
     }
 }
 
+thread_local! {
+    static BASE_DIRECTORY: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Resolves the relative paths deserialized by `f` from `directory` instead of the current one,
+/// e.g. for the included files
+pub(crate) fn with_base_directory<T>(directory: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let previous = BASE_DIRECTORY.replace(Some(directory.absolutize()?.to_path_buf()));
+    let result = f();
+    BASE_DIRECTORY.set(previous);
+
+    result
+}
+
+fn absolutize(path: &Path) -> io::Result<PathBuf> {
+    BASE_DIRECTORY.with_borrow(|directory| match directory {
+        Some(directory) => path.absolutize_from(directory).map(Cow::into_owned),
+        None => path.absolutize().map(Cow::into_owned),
+    })
+}
+
 serde_conv!(
     AbsPathBuf,
     PathBuf,
     |path: &PathBuf| path.to_string_lossy().to_string(),
     |path: String| -> Result<_> {
-        Path::new(&path)
-            .absolutize()
-            .map_err(Error::from)
-            .map(PathBuf::from)
+        absolutize(Path::new(&path)).map_err(Error::from)
     }
 );
 
@@ -1290,6 +1586,7 @@ serde_conv!(
                     dependency,
                     Dependency {
                         condition: Condition::Started,
+                        restart: None,
                     },
                 )
             })
@@ -1305,20 +1602,204 @@ serde_conv!(
         let mut parts = device.split(':');
 
         Ok(Device {
-            source: Path::new(parts.next().unwrap()).absolutize()?.to_path_buf(),
+            source: absolutize(Path::new(parts.next().unwrap()))?,
             target: parts.next().map(PathBuf::from),
             permissions: parts.next().map(ToString::to_string),
         })
     }
 );
 
+serde_conv!(
+    BytesOrU64,
+    Byte,
+    |bytes: &Byte| bytes.as_u64(),
+    |bytes: u64| -> Result<_, Infallible> { Ok(Byte::from_u64(bytes)) }
+);
+
+serde_conv!(
+    BytesWithSuffix,
+    Byte,
+    |bytes: &Byte| bytes.as_u64().to_string(),
+    |bytes: String| parse_bytes(&bytes)
+);
+
+/// Parses a size such as `512m` or `64mb`, the units being powers of 1024 like in Docker Compose
+pub(crate) fn parse_bytes(bytes: &str) -> Result<Byte> {
+    let Some(captures) = regex!(r"(?i)^(\d+)(?:\.(\d+))? ?(b|kb?|mb?|gb?)?$").captures(bytes) else {
+        bail!("invalid size \"{bytes}\", expected e.g. \"512m\" or \"1gb\"");
+    };
+
+    let factor = match captures
+        .get(3)
+        .map(|unit| unit.as_str().to_ascii_lowercase().chars().next().unwrap())
+    {
+        None | Some('b') => 1,
+        Some('k') => 1 << 10,
+        Some('m') => 1 << 20,
+        _ => 1 << 30,
+    };
+    let too_large = || anyhow!("size \"{bytes}\" is too large");
+    let mut value = captures[1]
+        .parse::<u128>()
+        .ok()
+        .and_then(|whole| whole.checked_mul(factor))
+        .ok_or_else(too_large)?;
+
+    if let Some(fraction) = captures.get(2) {
+        // The digits after the 19th are worth less than a byte whatever the unit
+        let fraction = &fraction.as_str()[..fraction.len().min(19)];
+        value = value
+            .checked_add(
+                fraction.parse::<u128>()? * factor / 10_u128.pow(u32::try_from(fraction.len())?),
+            )
+            .ok_or_else(too_large)?;
+    }
+
+    u64::try_from(value)
+        .map(Byte::from_u64)
+        .map_err(|_| too_large())
+}
+
+pub(crate) fn parse_duration(duration: &str) -> Result<Duration> {
+    if duration == "0" {
+        return Ok(Duration::ZERO);
+    }
+
+    let too_large = || anyhow!("duration \"{duration}\" is too large");
+    let mut nanos = 0_u128;
+    let mut end = 0;
+
+    for captures in regex!(r"(\d*)(?:\.(\d*))?(ns|us|µs|μs|ms|s|m|h)").captures_iter(duration) {
+        let (whole, fraction, unit) = (&captures[1], captures.get(2), &captures[3]);
+
+        if captures.get(0).unwrap().start() != end
+            || whole.is_empty() && fraction.is_none_or(|fraction| fraction.is_empty())
+        {
+            break;
+        }
+
+        let factor = match unit {
+            "ns" => 1,
+            "us" | "µs" | "μs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => 1_000_000_000,
+            "m" => 60_000_000_000,
+            _ => 3_600_000_000_000,
+        };
+        let whole = if whole.is_empty() {
+            0
+        } else {
+            whole.parse::<u128>().map_err(|_| too_large())?
+        };
+        // The digits after the 19th are worth less than a nanosecond whatever the unit
+        let fraction = fraction.map_or("", |fraction| {
+            &fraction.as_str()[..fraction.len().min(19)]
+        });
+
+        nanos = whole
+            .checked_mul(factor)
+            .and_then(|whole| nanos.checked_add(whole))
+            .ok_or_else(too_large)?;
+
+        if !fraction.is_empty() {
+            nanos += fraction.parse::<u128>()? * factor / 10_u128.pow(u32::try_from(fraction.len())?);
+        }
+
+        end = captures.get(0).unwrap().end();
+    }
+
+    if end == 0 || end != duration.len() {
+        bail!("invalid duration \"{duration}\", expected e.g. \"1m30s\" or \"500ms\"");
+    }
+
+    u64::try_from(nanos)
+        .map(Duration::from_nanos)
+        .map_err(|_| too_large())
+}
+
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let nanos = duration.subsec_nanos();
+
+    if duration.as_secs() == 0 {
+        return if nanos == 0 {
+            String::from("0s")
+        } else if nanos.is_multiple_of(1_000_000) {
+            format!("{}ms", nanos / 1_000_000)
+        } else if nanos.is_multiple_of(1_000) {
+            format!("{}us", nanos / 1_000)
+        } else {
+            format!("{nanos}ns")
+        };
+    }
+
+    let (hours, minutes, seconds) = (
+        duration.as_secs() / 3600,
+        duration.as_secs() % 3600 / 60,
+        duration.as_secs() % 60,
+    );
+    let fraction = if nanos > 0 {
+        format!(".{nanos:09}").trim_end_matches('0').to_string()
+    } else {
+        String::new()
+    };
+
+    if hours > 0 {
+        format!("{hours}h{minutes}m{seconds}{fraction}s")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds}{fraction}s")
+    } else {
+        format!("{seconds}{fraction}s")
+    }
+}
+
 serde_conv!(
     DurationWithSuffix,
     Duration,
-    |duration: &Duration| format_duration(*duration).to_string(),
+    |duration: &Duration| format_duration(*duration),
     |duration: String| parse_duration(&duration)
 );
 
+serde_conv!(
+    ExtendsOrString,
+    Extends,
+    |extends: &Extends| extends.service.clone(),
+    |service| -> Result<_, Infallible> {
+        Ok(Extends {
+            service,
+            file: None,
+        })
+    }
+);
+
+serde_conv!(
+    IncludeOrString,
+    Include,
+    |include: &Include| include
+        .path
+        .first()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default(),
+    |path: String| -> Result<_, Infallible> {
+        Ok(Include {
+            path: vec![PathBuf::from(path)],
+            env_file: Vec::new(),
+            project_directory: None,
+        })
+    }
+);
+
+serde_conv!(
+    EnvFileOrString,
+    EnvFile,
+    |env_file: &EnvFile| env_file.path.to_string_lossy().to_string(),
+    |path: String| -> Result<_, Infallible> {
+        Ok(EnvFile {
+            path: PathBuf::from(path),
+            required: true,
+        })
+    }
+);
+
 serde_conv!(
     FileReferenceOrString,
     FileReference,
@@ -1458,13 +1939,14 @@ serde_conv!(
 );
 
 pub(crate) fn parse_port(port: &str) -> Result<Port, Infallible> {
-    let mut parts = port.split(':').rev();
+    let mut parts = port.rsplitn(3, ':');
     let container_port = parts.next().unwrap();
-    let mut container_parts = container_port.split('/');
-    let target = container_parts.next().unwrap().to_string();
+    let (target, protocol) = container_port
+        .split_once('/')
+        .unwrap_or((container_port, "tcp"));
 
     Ok(Port {
-        target,
+        target: target.to_string(),
         published: parts.next().and_then(|part| {
             if part.is_empty() {
                 None
@@ -1473,9 +1955,7 @@ pub(crate) fn parse_port(port: &str) -> Result<Port, Infallible> {
             }
         }),
         host_ip: parts.next().map(ToString::to_string),
-        protocol: container_parts
-            .next()
-            .map_or_else(|| String::from("tcp"), ToString::to_string),
+        protocol: protocol.to_string(),
     })
 }
 
@@ -1542,8 +2022,12 @@ pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
             target = dst.to_string();
         }
         [src, dst] if dst.starts_with('/') => {
+            if src.is_empty() {
+                bail!("{mount}: empty source");
+            }
+
             if src.starts_with('/') || src.starts_with('.') {
-                r#type = ServiceVolumeType::Bind(Path::new(src).absolutize()?.to_path_buf());
+                r#type = ServiceVolumeType::Bind(absolutize(Path::new(src))?);
                 bind = Some(ServiceVolumeBind {
                     create_host_path: Some(true),
                     ..ServiceVolumeBind::default()
@@ -1559,8 +2043,12 @@ pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
             options = opts;
         }
         [src, dst, opts] => {
+            if src.is_empty() {
+                bail!("{mount}: empty source");
+            }
+
             if src.starts_with('/') || src.starts_with('.') {
-                r#type = ServiceVolumeType::Bind(Path::new(src).absolutize()?.to_path_buf());
+                r#type = ServiceVolumeType::Bind(absolutize(Path::new(src))?);
                 bind = Some(ServiceVolumeBind {
                     create_host_path: Some(true),
                     ..ServiceVolumeBind::default()
@@ -1577,6 +2065,10 @@ pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
         }
     }
 
+    if target.is_empty() {
+        bail!("{mount}: empty container path");
+    }
+
     let options = options.split(',');
     let mut unused = Vec::new();
 
@@ -1607,11 +2099,7 @@ pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
     }
 
     if !unused.is_empty() {
-        eprintln!(
-            "{} Unsupported/unknown mount options: {}",
-            *STYLED_WARNING,
-            unused.join(", ")
-        );
+        bail!("{mount}: unknown mount options: {}", unused.join(", "));
     }
 
     Ok(ServiceVolume {
@@ -1624,6 +2112,34 @@ pub(crate) fn parse_service_volume(mount: &str) -> Result<ServiceVolume> {
     })
 }
 
+fn parse_restart_policy(policy: &str) -> Result<RestartPolicy> {
+    match policy {
+        "no" => Ok(RestartPolicy::No),
+        "always" => Ok(RestartPolicy::Always),
+        "on-failure" => Ok(RestartPolicy::OnFailure(None)),
+        "unless-stopped" => Ok(RestartPolicy::UnlessStopped),
+        _ => {
+            if let Some(retries) = policy.strip_prefix("on-failure:") {
+                retries
+                    .parse()
+                    .map(|retries| RestartPolicy::OnFailure(Some(retries)))
+                    .map_err(|_| anyhow!("Invalid maximum retries \"{retries}\" for the restart policy"))
+            } else {
+                bail!(
+                    "Invalid restart policy \"{policy}\", expected one of: no, always, on-failure[:max-retries], unless-stopped"
+                )
+            }
+        }
+    }
+}
+
+serde_conv!(
+    RestartPolicyOrString,
+    RestartPolicy,
+    ToString::to_string,
+    |policy: String| parse_restart_policy(&policy)
+);
+
 serde_conv!(
     ServiceVolumeOrString,
     ServiceVolume,
@@ -1631,6 +2147,212 @@ serde_conv!(
     |mount: String| parse_service_volume(&mount)
 );
 
+/// Describes the conversions above by the representation they deserialize from
+macro_rules! schema_from {
+    ($($converter:ident => $representation:ty),+ $(,)?) => {
+        $(
+            impl<T> JsonSchemaAs<T> for $converter {
+                fn inline_schema() -> bool {
+                    true
+                }
+
+                fn schema_name() -> Cow<'static, str> {
+                    Cow::Borrowed(stringify!($converter))
+                }
+
+                fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+                    <$representation>::json_schema(generator)
+                }
+            }
+        )+
+    };
+}
+
+schema_from!(
+    AbsPathBuf => String,
+    BytesOrU64 => u64,
+    BytesWithSuffix => String,
+    CommandOrString => String,
+    DependsOnVec => Vec<String>,
+    DeviceOrString => String,
+    DurationWithSuffix => String,
+    ExtendsOrString => String,
+    IncludeOrString => String,
+    EnvFileOrString => String,
+    FileReferenceOrString => String,
+    MappingWithColonEmpty => Vec<String>,
+    MappingWithEqualsEmpty => Vec<String>,
+    MappingWithEqualsNoNull => Vec<String>,
+    MappingWithEqualsNull => Vec<String>,
+    NetworksVec => Vec<String>,
+    PortOrString => String,
+    PortOrU16 => u16,
+    SecurityOptVec => Vec<String>,
+    RestartPolicyOrString => String,
+    ServiceVolumeOrString => String,
+);
+
+/// Implements `Serialize` and `Deserialize` for the types with `x-` extensions, setting them aside
+/// before the derived implementations see the other fields
+macro_rules! with_extensions {
+    ($($type:ty),+ $(,)?) => {
+        $(
+            impl<'de> Deserialize<'de> for $type {
+                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where
+                    D: Deserializer<'de>,
+                {
+                    let mut extensions = IndexMap::new();
+                    let mut value = Self::deserialize(Extensions {
+                        deserializer,
+                        extensions: &mut extensions,
+                    })?;
+                    value.extensions = extensions;
+
+                    Ok(value)
+                }
+            }
+
+            impl Serialize for $type {
+                fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+                where
+                    S: Serializer,
+                {
+                    Self::serialize(self, serializer)
+                }
+            }
+        )+
+    };
+}
+
+with_extensions!(Compose, Service, Network, Volume, Secret);
+
+/// Collects the `x-` fields of a mapping, which `serde` cannot match by prefix, while the others
+/// go through the wrapped deserializer so that the unknown ones are still reported
+struct Extensions<'a, D> {
+    deserializer: D,
+    extensions: &'a mut IndexMap<String, Value>,
+}
+
+impl<'de, D> Deserializer<'de> for Extensions<'_, D>
+where
+    D: Deserializer<'de>,
+{
+    type Error = D::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserializer.deserialize_any(Extensions {
+            deserializer: visitor,
+            extensions: self.extensions,
+        })
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserializer.deserialize_struct(
+            name,
+            fields,
+            Extensions {
+                deserializer: visitor,
+                extensions: self.extensions,
+            },
+        )
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+impl<'de, V> Visitor<'de> for Extensions<'_, V>
+where
+    V: Visitor<'de>,
+{
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.deserializer.expecting(f)
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.deserializer.visit_seq(seq)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.deserializer.visit_map(Extensions {
+            deserializer: map,
+            extensions: self.extensions,
+        })
+    }
+}
+
+impl<'de, A> MapAccess<'de> for Extensions<'_, A>
+where
+    A: MapAccess<'de>,
+{
+    type Error = A::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        while let Some(key) = self.deserializer.next_key::<String>()? {
+            if key.starts_with("x-") {
+                let value = self.deserializer.next_value()?;
+                self.extensions.insert(key, value);
+            } else {
+                return seed.deserialize(key.into_deserializer()).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.deserializer.next_value_seed(seed)
+    }
+}
+
+/// A number of bytes, or a string with a unit such as `512m`
+struct ByteSize;
+
+impl JsonSchema for ByteSize {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        Cow::Borrowed("ByteSize")
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": ["integer", "string"]
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -1663,4 +2385,150 @@ mod tests {
             format!("{:#?}", serde_yaml::from_str::<Compose>(&expected).unwrap())
         );
     }
+
+    #[test]
+    fn merge_integer_keys() {
+        let mut result = serde_yaml::from_str::<Compose>("x-map:\n  1: a\n  2: b\n").unwrap();
+        result.merge(serde_yaml::from_str("x-map:\n  1: c\n").unwrap());
+
+        assert_eq!(
+            result.extensions["x-map"],
+            serde_yaml::from_str::<Value>("1: c\n2: b\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn merge_forms() {
+        let mut value = serde_yaml::from_str::<Value>(
+            "depends_on: [db]\nextra_hosts: ['db:10.0.0.2']\nlabels: [a=1, b]\n",
+        )
+        .unwrap();
+        super::merge(
+            &mut value,
+            serde_yaml::from_str(
+                "depends_on:\n  cache:\n    condition: service_healthy\nextra_hosts:\n  cache: 10.0.0.3\nlabels:\n  a: 2\n",
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            value,
+            serde_yaml::from_str::<Value>(
+                "depends_on:\n  db:\n    condition: service_started\n  cache:\n    condition: service_healthy\nextra_hosts:\n  db: 10.0.0.2\n  cache: 10.0.0.3\nlabels:\n  a: 2\n  b: ''\n",
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn durations() {
+        for (duration, expected) in [
+            ("0", Duration::ZERO),
+            ("500ms", Duration::from_millis(500)),
+            ("1m30s", Duration::from_secs(90)),
+            ("1.5h", Duration::from_mins(90)),
+            ("2h45m10.5s", Duration::from_millis(9_910_500)),
+            ("10us", Duration::from_micros(10)),
+            ("7ns", Duration::from_nanos(7)),
+        ] {
+            assert_eq!(parse_duration(duration).unwrap(), expected);
+            assert_eq!(parse_duration(&format_duration(expected)).unwrap(), expected);
+        }
+
+        assert_eq!(
+            parse_duration("1.0000000000000000000000000000000000000001s").unwrap(),
+            Duration::from_secs(1)
+        );
+
+        for duration in [
+            "",
+            "10",
+            "1x",
+            "1m 30s",
+            "s",
+            ".s",
+            "-1s",
+            "1s2",
+            "99999999999999999999999999999999999h",
+            "999999999999999999999999999999999999999999h",
+            "5124096h",
+        ] {
+            assert_matches!(parse_duration(duration), Err(_));
+        }
+
+        assert_eq!(format_duration(Duration::from_secs(3661)), "1h1m1s");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1.5s");
+    }
+
+    #[test]
+    fn bytes() {
+        for (bytes, expected) in [
+            ("0", 0),
+            ("10b", 10),
+            ("1k", 1024),
+            ("512m", 512 * 1024 * 1024),
+            ("64mb", 64 * 1024 * 1024),
+            ("2GB", 2 * 1024 * 1024 * 1024),
+            ("1.5g", 1536 * 1024 * 1024),
+            ("100 kb", 100 * 1024),
+        ] {
+            assert_eq!(parse_bytes(bytes).unwrap().as_u64(), expected);
+        }
+
+        assert_eq!(
+            parse_bytes("1.00000000000000000000000000000000000000001g")
+                .unwrap()
+                .as_u64(),
+            1024 * 1024 * 1024
+        );
+
+        for bytes in [
+            "",
+            "m",
+            "1x",
+            "-1m",
+            "1mib",
+            "1.m",
+            "1bb",
+            "17179869184g",
+            "999999999999999999999999999999999999999999g",
+        ] {
+            assert_matches!(parse_bytes(bytes), Err(_));
+        }
+
+        let service = serde_yaml::from_str::<Service>("mem_limit: 64mb\nshm_size: 1073741824\n").unwrap();
+        assert_eq!(service.mem_limit.unwrap().as_u64(), 64 * 1024 * 1024);
+        assert_eq!(service.shm_size.unwrap().as_u64(), 1024 * 1024 * 1024);
+        assert_matches!(serde_yaml::from_str::<Service>("mem_limit: 1x\n"), Err(_));
+    }
+
+    #[test]
+    fn service_volumes() {
+        let volume = parse_service_volume("./data:/var/lib:ro,z").unwrap();
+        assert_matches!(volume.r#type, ServiceVolumeType::Bind(source) if source.is_absolute());
+        assert_eq!(volume.target, PathBuf::from("/var/lib"));
+        assert_eq!(volume.read_only, Some(true));
+        assert_eq!(volume.bind.unwrap().selinux, Some(String::from("z")));
+
+        let volume = parse_service_volume("/data:/data").unwrap();
+        assert_matches!(volume.r#type, ServiceVolumeType::Bind(source) if source == Path::new("/data"));
+
+        let volume = parse_service_volume("data:/data:nocopy").unwrap();
+        assert_matches!(volume.r#type, ServiceVolumeType::Volume(Some(source)) if source == "data");
+
+        for mount in [":/data", "data::ro", "data:/data:rx", "/data:foo", "a:b:c:d"] {
+            assert_matches!(parse_service_volume(mount), Err(_));
+        }
+    }
+
+    #[test]
+    fn restart_policies() {
+        for policy in ["no", "always", "on-failure", "on-failure:3", "unless-stopped"] {
+            assert_eq!(parse_restart_policy(policy).unwrap().to_string(), policy);
+        }
+
+        for policy in ["alwyas", "on-failure:", "on-failure:-1", "on-failure:x", ""] {
+            assert_matches!(parse_restart_policy(policy), Err(_));
+        }
+    }
 }
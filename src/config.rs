@@ -1,5 +1,8 @@
 use std::{
+    collections::HashSet,
     env,
+    ffi::OsStr,
+    fs, iter,
     path::{Path, PathBuf},
 };
 
@@ -8,11 +11,12 @@ use figment::{
     providers::{Env, Serialized},
     Figment,
 };
+use indexmap::{IndexMap, IndexSet};
 use itertools::iproduct;
 use once_cell::sync::Lazy;
 use path_absolutize::Absolutize;
 
-use crate::Flags;
+use crate::{compose, utils, utils::Failure, Flags};
 
 static COMPOSE_FILE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
     iproduct!(["compose", "docker-compose"], ["yaml", "yml"])
@@ -20,14 +24,16 @@ static COMPOSE_FILE_NAMES: Lazy<Vec<String>> = Lazy::new(|| {
         .collect()
 });
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub(crate) struct Config {
     pub(crate) project_name: Option<String>,
     pub(crate) files: Vec<PathBuf>,
     pub(crate) profiles: Vec<String>,
-    pub(crate) env_file: PathBuf,
+    pub(crate) env_files: Vec<PathBuf>,
+    pub(crate) variables: IndexMap<String, String>,
     pub(crate) project_directory: PathBuf,
     pub(crate) dry_run: bool,
+    pub(crate) warn_as_error: bool,
 }
 
 fn find(directory: &Path, files: &[String]) -> Result<PathBuf> {
@@ -45,22 +51,72 @@ fn find(directory: &Path, files: &[String]) -> Result<PathBuf> {
     if let Some(parent) = directory.parent() {
         find(parent, files)
     } else {
-        bail!("Compose file not found in the working directory or its parent directories");
+        bail!(Failure::NotFound
+            .error("Compose file not found in the working directory or its parent directories"));
+    }
+}
+
+// Windows accepts both separators, which are unified for the paths to be displayed consistently,
+// whereas a backslash is a valid file name character elsewhere
+fn normalize(path: PathBuf) -> PathBuf {
+    if cfg!(windows) {
+        match path.to_str() {
+            Some(path) => PathBuf::from(path.replace('/', "\\")),
+            None => path,
+        }
+    } else {
+        path
     }
 }
 
 fn resolve(flags: &Flags) -> Result<Config> {
     let current_dir = env::current_dir()?;
+    // The profiles from `COMPOSE_PROFILES` are enabled alongside the ones from the flags
+    let profiles = env::var("COMPOSE_PROFILES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::to_string)
+        .chain(flags.profile.iter().flatten().cloned())
+        .map(|profile| profile.trim().to_string())
+        .filter(|profile| !profile.is_empty())
+        .collect::<IndexSet<_>>();
     let flags = Figment::new()
-        .merge(Env::prefixed("COMPOSE_").ignore(&["env_file", "project_directory", "dry_run"]))
+        // `COMPOSE_PROJECT_NAME` only comes after the name declared in the Compose files
+        .merge(Env::prefixed("COMPOSE_").ignore(&[
+            "project_name",
+            "env_file",
+            "no_env_file",
+            "project_directory",
+            "profiles",
+            "variable",
+            "dry_run",
+            "quiet_warnings",
+            "warn_as_error",
+            "log_format",
+            "no_color",
+        ]))
         .merge(Serialized::defaults(flags))
         .extract::<Flags>()?;
 
-    let files = if let Some(files) = flags.file {
+    // Empty entries can come from the separators in `COMPOSE_FILE`, e.g. a trailing one
+    let files = flags.file.map(|files| {
         files
             .into_iter()
+            .filter(|file| !file.as_os_str().is_empty())
             .map(|file| {
-                if file.as_os_str() == "-" {
+                if compose::is_remote(&file) {
+                    file
+                } else {
+                    normalize(file)
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+    let files = if let Some(files) = files.filter(|files| !files.is_empty()) {
+        files
+            .into_iter()
+            .map(|file| {
+                if file.as_os_str() == "-" || compose::is_remote(&file) {
                     Ok(file)
                 } else {
                     file.absolutize_from(&current_dir).map(PathBuf::from)
@@ -73,24 +129,29 @@ fn resolve(flags: &Flags) -> Result<Config> {
             &COMPOSE_FILE_NAMES,
         )?;
 
-        let override_file = file.with_extension(if let Some(extension) = file.extension() {
-            format!("override.{}", extension.to_string_lossy())
-        } else {
-            String::from("override")
-        });
+        // The override file matching the Compose file is preferred, then any other one like Docker
+        // Compose does, e.g. `compose.override.yml` for `compose.yaml`
+        let override_file = iter::once(file.clone())
+            .chain(COMPOSE_FILE_NAMES.iter().map(|name| file.with_file_name(name)))
+            .map(|file| {
+                file.with_extension(if let Some(extension) = file.extension() {
+                    format!("override.{}", extension.to_string_lossy())
+                } else {
+                    String::from("override")
+                })
+            })
+            .find(|file| file.is_file());
 
-        if override_file.is_file() {
-            vec![&file, &override_file]
-        } else {
-            vec![&file]
-        }
-        .into_iter()
-        .map(|file| file.absolutize_from(&current_dir).map(PathBuf::from))
-        .collect::<Result<Vec<_>, _>>()?
+        iter::once(file)
+            .chain(override_file)
+            .map(|file| file.absolutize_from(&current_dir).map(PathBuf::from))
+            .collect::<Result<Vec<_>, _>>()?
     };
 
-    let project_directory = if let Some(dir) = flags.project_directory {
+    let project_directory = if let Some(dir) = flags.project_directory.map(normalize) {
         dir.absolutize_from(&current_dir)?.to_path_buf()
+    } else if compose::is_remote(&files[0]) {
+        current_dir
     } else {
         files[0]
             .parent()
@@ -102,32 +163,164 @@ fn resolve(flags: &Flags) -> Result<Config> {
     Ok(Config {
         project_name: flags.project_name,
         files,
-        profiles: flags.profile.unwrap_or_default(),
+        profiles: profiles.into_iter().collect(),
+        variables: flags.variable.unwrap_or_default().into_iter().collect(),
         project_directory,
         dry_run: flags.dry_run.unwrap_or_default(),
+        warn_as_error: flags.warn_as_error.unwrap_or_default(),
         ..Config::default()
     })
 }
 
+// dotenvy substitutes variables by itself with a limited syntax, so dollar signs are escaped for the
+// values to go through the same interpolation as the Compose file instead
+fn escape_dollars(contents: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = contents.chars();
+    let (mut strong_quote, mut weak_quote, mut comment, mut whitespace) =
+        (false, false, false, true);
+
+    while let Some(c) = chars.next() {
+        match c {
+            _ if comment => comment = c != '\n',
+            '#' if !strong_quote && !weak_quote && whitespace => comment = true,
+            '\'' if !weak_quote => strong_quote = !strong_quote,
+            '"' if !strong_quote => weak_quote = !weak_quote,
+            '$' if strong_quote => escaped.push('$'),
+            '$' => escaped.push('\\'),
+            '\\' if !strong_quote => match chars.next() {
+                Some('$') => {
+                    escaped.push_str("\\$\\$");
+                    whitespace = false;
+                    continue;
+                }
+                Some(next) => {
+                    escaped.push('\\');
+                    escaped.push(next);
+                    whitespace = false;
+                    continue;
+                }
+                None => {}
+            },
+            _ => {}
+        }
+
+        escaped.push(c);
+        whitespace = c.is_whitespace();
+    }
+
+    escaped
+}
+
+/// Parses the variables of an environment file, leaving their values uninterpolated
+pub(crate) fn parse_env_file(path: &Path, contents: &str) -> Result<Vec<(String, String)>> {
+    dotenvy::from_read_iter(escape_dollars(contents).as_bytes())
+        .map(|item| item.with_context(|| anyhow!("{} could not be parsed", path.display())))
+        .collect()
+}
+
 pub(crate) fn load(flags: Flags) -> Result<Config> {
+    utils::set_quiet_warnings(flags.quiet_warnings.unwrap_or_default());
+
     let config = resolve(&flags)?;
-    let env_file = flags
-        .env_file
-        .clone()
-        .unwrap_or_else(|| config.project_directory.join(".env"));
-
-    dotenvy::from_path(&env_file)
-        .with_context(|| anyhow!("{} not found", env_file.display()))
-        .or_else(|err| {
-            if flags.env_file.is_some() {
-                Err(err)
-            } else {
-                Ok(())
+    let env_files = if flags.no_env_file.unwrap_or_default() {
+        Vec::new()
+    } else {
+        flags
+            .env_file
+            .clone()
+            .map_or_else(
+                || vec![config.project_directory.join(".env")],
+                |env_files| env_files.into_iter().map(normalize).collect(),
+            )
+    };
+
+    let existing = env::vars_os().map(|(key, _)| key).collect::<HashSet<_>>();
+    let mut variables = compose::variables(&config);
+
+    for env_file in &env_files {
+        let contents = match fs::read_to_string(env_file) {
+            Ok(contents) => contents,
+            Err(_) if flags.env_file.is_none() => continue,
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    Failure::NotFound.error(format_args!("{} not found", env_file.display()))
+                })
+            }
+        };
+
+        for (key, value) in parse_env_file(env_file, &contents)? {
+            // Variables from the environment take precedence over the ones from the files, which
+            // can reference the ones defined before them
+            if !existing.contains(OsStr::new(&key)) {
+                let value = compose::interpolate_str(&value, &variables).with_context(|| {
+                    Failure::Interpolation.error(format_args!("{}: {key}", env_file.display()))
+                })?;
+
+                env::set_var(&key, &value);
+                variables.insert(key, value);
             }
-        })?;
+        }
+    }
 
     let mut config = resolve(&flags)?;
-    config.env_file = env_file;
+    config.env_files = env_files;
 
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn env_file_references() {
+        let flags = Flags {
+            project_name: None,
+            file: Some(vec![PathBuf::from(
+                "tests/fixtures/env-references/compose.yaml",
+            )]),
+            profile: None,
+            env_file: None,
+            no_env_file: None,
+            variable: None,
+            project_directory: None,
+            path_separator: None,
+            quiet_warnings: None,
+            warn_as_error: None,
+            log_format: None,
+            no_color: None,
+            dry_run: None,
+        };
+
+        temp_env::with_vars(
+            [("BRANCH", Some("main")), ("A", None), ("TAG", None)],
+            || {
+                load(flags).unwrap();
+
+                assert_eq!(env::var("A").as_deref(), Ok("one"));
+                assert_eq!(env::var("TAG").as_deref(), Ok("main-one"));
+            },
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn normalize_mixed_separators() {
+        assert_eq!(
+            normalize(PathBuf::from("C:/project\\sub/compose.yaml")),
+            PathBuf::from("C:\\project\\sub\\compose.yaml")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normalize_backslashes() {
+        assert_eq!(
+            normalize(PathBuf::from("sub\\compose.yaml")),
+            PathBuf::from("sub\\compose.yaml")
+        );
+    }
+}
@@ -1,6 +1,6 @@
 automod::dir!("src");
 
-use std::{env, path::PathBuf};
+use std::{env, path::PathBuf, process};
 
 use anyhow::Result;
 use clap::{ArgAction, Parser};
@@ -9,7 +9,10 @@ use serde_with::{
     formats::CommaSeparator, serde_as, skip_serializing_none, PickFirst, StringWithSeparator,
 };
 
-use self::{commands::Command, utils::PathSeparator};
+use self::{
+    commands::Command,
+    utils::{parse_key_val, LogFormat, PathSeparator},
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, next_display_order = None)]
@@ -35,30 +38,81 @@ pub(crate) struct Flags {
     pub(crate) file: Option<Vec<PathBuf>>,
 
     /// Specify a profile to enable
-    #[arg(long)]
+    #[arg(long, global = true)]
     #[serde_as(as = "Option<PickFirst<(_, StringWithSeparator::<CommaSeparator, String>)>>")]
     #[serde(rename = "profiles")]
     pub(crate) profile: Option<Vec<String>>,
 
-    /// Specify an alternate environment file
+    /// Specify alternate environment files
     #[arg(long)]
-    pub(crate) env_file: Option<PathBuf>,
+    pub(crate) env_file: Option<Vec<PathBuf>>,
+
+    /// Don't load the default environment file
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "env_file")]
+    pub(crate) no_env_file: Option<bool>,
+
+    /// Set a variable for interpolation, taking precedence over the environment
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_key_val::<String, String>)]
+    pub(crate) variable: Option<Vec<(String, String)>>,
 
-    /// Specify an alternate working directory
+    /// Specify an alternate working directory (default: the directory of the first Compose file)
     #[arg(long)]
     pub(crate) project_directory: Option<PathBuf>,
 
     #[arg(skip)]
     pub(crate) path_separator: Option<String>,
 
+    /// Don't print warnings
+    #[arg(short = 'W', long, action = ArgAction::SetTrue, global = true)]
+    pub(crate) quiet_warnings: Option<bool>,
+
+    /// Fail when the Compose files raise warnings
+    #[arg(long, action = ArgAction::SetTrue, global = true, conflicts_with = "quiet_warnings")]
+    pub(crate) warn_as_error: Option<bool>,
+
+    /// Format of the warnings and errors
+    #[arg(long, value_enum, global = true)]
+    pub(crate) log_format: Option<LogFormat>,
+
+    /// Disable colors in the output
+    #[arg(long, action = ArgAction::SetTrue, global = true)]
+    pub(crate) no_color: Option<bool>,
+
     /// Only show the Podman commands that will be executed
     #[arg(long, action = ArgAction::SetTrue, global = true)]
     pub(crate) dry_run: Option<bool>,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     let args = Args::parse();
+
+    if args.flags.no_color.unwrap_or_default()
+        || env::var_os("NO_COLOR").is_some_and(|no_color| !no_color.is_empty())
+    {
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+
+    let log_format = args.flags.log_format.unwrap_or_default();
+    utils::set_log_format(log_format);
+
+    if let Err(err) = run(args).await {
+        match log_format {
+            LogFormat::Text => eprintln!("Error: {err:?}"),
+            LogFormat::Json => utils::error(format_args!("{err:#}")),
+        }
+
+        process::exit(utils::exit_code(&err));
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
+    // The schema does not depend on any project, so there may not be a Compose file to find
+    if let Command::Schema(args) = args.command {
+        return commands::schema::run(args);
+    }
+
     let config = config::load(args.flags)?;
 
     env::set_current_dir(&config.project_directory)?;
@@ -14,7 +14,7 @@ use futures::{
 };
 use once_cell::sync::Lazy;
 use tokio::{
-    io::{AsyncBufReadExt, BufReader},
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     process::Command,
 };
 use tokio_stream::wrappers::LinesStream;
@@ -83,14 +83,52 @@ impl Podman {
         }
     }
 
+    /// Runs the command with `input` written to its standard input
+    pub(crate) async fn run_with_input<I, S>(&self, args: I, input: &str) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        if self.dry_run {
+            self.run(args).await
+        } else {
+            self.execute(args, Some(input)).await
+        }
+    }
+
     pub(crate) async fn force_run<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.execute(args, None).await
+    }
+
+    async fn execute<I, S>(&self, args: I, input: Option<&str>) -> Result<String>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
         let mut command = self.command(args);
 
-        let output = command.output().await.with_context(|| {
+        let output = async {
+            if let Some(input) = input {
+                let mut child = command
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                let mut stdin = child.stdin.take().unwrap();
+                stdin.write_all(input.as_bytes()).await?;
+                drop(stdin);
+
+                child.wait_with_output().await
+            } else {
+                command.output().await
+            }
+        }
+        .await
+        .with_context(|| {
             anyhow!(
                 "`{} {}` cannot be executed",
                 command.as_std().get_program().to_string_lossy(),
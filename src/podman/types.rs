@@ -23,6 +23,12 @@ pub(crate) struct ContainerLabels {
     pub(crate) container_number: Option<usize>,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct Image {
+    pub(crate) repo_digests: VecDeque<String>,
+}
+
 #[derive(Deserialize, Debug)]
 pub(crate) struct Network {
     pub(crate) name: String,
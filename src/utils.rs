@@ -1,9 +1,12 @@
 use std::{
+    borrow::Cow,
+    cell::RefCell,
     env,
     error::Error,
-    fmt::{self, Formatter},
+    fmt::{self, Display, Formatter},
     marker::PhantomData,
     str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use anyhow::{anyhow, Result};
@@ -11,13 +14,16 @@ use console::{style, StyledObject};
 use once_cell::sync::Lazy;
 use serde::{
     de::{self, Visitor},
-    Deserializer, Serialize, Serializer,
+    Deserialize, Deserializer, Serialize, Serializer,
 };
-use serde_with::{formats::Separator, DeserializeAs, SerializeAs};
+use schemars::{json_schema, Schema, SchemaGenerator};
+use serde_with::{formats::Separator, schemars_1::JsonSchemaAs, DeserializeAs, SerializeAs};
 use sha2::{Digest as _, Sha256};
 
+pub(crate) static STYLED_ERROR: Lazy<StyledObject<&str>> =
+    Lazy::new(|| style("Error:").for_stderr().red().bold());
 pub(crate) static STYLED_WARNING: Lazy<StyledObject<&str>> =
-    Lazy::new(|| style("Warning:").yellow().bold());
+    Lazy::new(|| style("Warning:").for_stderr().yellow().bold());
 
 pub(crate) fn parse_container_path<T, U>(s: &str) -> Result<(Option<T>, U)>
 where
@@ -74,6 +80,141 @@ macro_rules! regex {
 
 pub(crate) use regex;
 
+static QUIET_WARNINGS: AtomicBool = AtomicBool::new(false);
+static JSON_LOGS: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static COLLECTED_WARNINGS: RefCell<Option<Vec<Logged>>> = const { RefCell::new(None) };
+}
+
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+pub(crate) fn set_quiet_warnings(quiet: bool) {
+    QUIET_WARNINGS.store(quiet, Ordering::Relaxed);
+}
+
+pub(crate) fn set_log_format(format: LogFormat) {
+    JSON_LOGS.store(matches!(format, LogFormat::Json), Ordering::Relaxed);
+}
+
+/// A message with the file and the property it is about, given as fields of the JSON logs
+#[derive(Clone, Default, PartialEq, Debug)]
+pub(crate) struct Logged {
+    pub(crate) message: String,
+    pub(crate) file: Option<String>,
+    pub(crate) path: Option<String>,
+}
+
+impl Display for Logged {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+fn log(level: &str, styled_level: &StyledObject<&str>, logged: &Logged) {
+    if JSON_LOGS.load(Ordering::Relaxed) {
+        let mut fields = serde_json::json!({ "level": level, "message": logged.message });
+
+        for (key, value) in [("file", &logged.file), ("path", &logged.path)] {
+            if let Some(value) = value {
+                fields[key] = serde_json::Value::from(value.as_str());
+            }
+        }
+
+        eprintln!("{fields}");
+    } else {
+        eprintln!("{styled_level} {}", logged.message);
+    }
+}
+
+/// Runs `f`, returning the warnings it raises instead of printing them
+pub(crate) fn collect_warnings<T>(f: impl FnOnce() -> T) -> (T, Vec<Logged>) {
+    let previous = COLLECTED_WARNINGS.with(|warnings| warnings.replace(Some(Vec::new())));
+    let value = f();
+    let warnings = COLLECTED_WARNINGS.with(|warnings| warnings.replace(previous));
+
+    (value, warnings.unwrap_or_default())
+}
+
+pub(crate) fn warn(message: impl Display) {
+    warn_logged(Logged {
+        message: message.to_string(),
+        ..Logged::default()
+    });
+}
+
+pub(crate) fn warn_logged(logged: Logged) {
+    let logged = COLLECTED_WARNINGS.with(|warnings| match warnings.borrow_mut().as_mut() {
+        Some(warnings) => {
+            warnings.push(logged);
+            None
+        }
+        None => Some(logged),
+    });
+
+    if let Some(logged) = logged.filter(|_| !QUIET_WARNINGS.load(Ordering::Relaxed)) {
+        log("warning", &STYLED_WARNING, &logged);
+    }
+}
+
+pub(crate) fn error(message: impl Display) {
+    error_logged(&Logged {
+        message: message.to_string(),
+        ..Logged::default()
+    });
+}
+
+pub(crate) fn error_logged(logged: &Logged) {
+    log("error", &STYLED_ERROR, logged);
+}
+
+/// Classes of errors that haddock exits with a distinct status code for
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Failure {
+    NotFound = 1,
+    Syntax = 2,
+    Specification = 3,
+    Interpolation = 4,
+}
+
+impl Failure {
+    pub(crate) fn error(self, message: impl Display) -> Classified {
+        Classified(self, message.to_string())
+    }
+}
+
+/// An error message tagged with its class, found anywhere in the chain of an error
+#[derive(Debug)]
+pub(crate) struct Classified(Failure, String);
+
+impl Display for Classified {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.1)
+    }
+}
+
+impl Error for Classified {}
+
+pub(crate) fn exit_code(err: &anyhow::Error) -> i32 {
+    // Context is searched from the outermost layer, the error being classified by its latest cause
+    err.downcast_ref::<Classified>()
+        .map_or(1, |err| err.0 as i32)
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::utils::warn(format_args!($($arg)*))
+    };
+}
+
+pub(crate) use warning;
+
 pub(crate) trait Digest {
     fn digest(&self) -> String;
 }
@@ -102,7 +243,7 @@ where
     {
         struct AnyVisitor<T>(PhantomData<T>);
 
-        impl<'de, T> Visitor<'de> for AnyVisitor<T>
+        impl<T> Visitor<'_> for AnyVisitor<T>
         where
             T: From<String>,
         {
@@ -164,6 +305,22 @@ where
     }
 }
 
+impl<T> JsonSchemaAs<T> for DisplayFromAny {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        Cow::Borrowed("DisplayFromAny")
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": ["string", "number", "boolean"]
+        })
+    }
+}
+
 pub(crate) struct PathSeparator;
 
 impl Separator for PathSeparator {